@@ -0,0 +1,165 @@
+use crate::client::Client;
+use crate::config::Config;
+use crate::notifier::Urgency;
+use crate::{fetch_readings, tracker, Readings};
+use anyhow::Result;
+use serde_json::json;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Output formats of the `bar` subcommand
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Format {
+    /// JSON objects for a waybar custom module
+    Waybar,
+    /// a single line, coloured with polybar's format tags
+    Polybar,
+    /// i3blocks' full text, short text and colour lines
+    I3blocks,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "waybar" => Ok(Format::Waybar),
+            "polybar" => Ok(Format::Polybar),
+            "i3blocks" => Ok(Format::I3blocks),
+            _ => Err(format!(
+                "invalid format `{}`: expected waybar, polybar or i3blocks",
+                s
+            )),
+        }
+    }
+}
+
+/// What the bar shows for a single update
+struct Module {
+    /// the most advanced region, e.g. `Europe 5/6`
+    text: String,
+    /// just the level of the most advanced region
+    short: String,
+    /// every tracked region, one per line
+    tooltip: String,
+    level: Option<i32>,
+    /// whether the most advanced region's level is based on a stale report
+    stale: bool,
+}
+
+impl Module {
+    fn new(Readings { readings, failed }: &Readings) -> Self {
+        let mut tooltip = Vec::new();
+        for reading in readings {
+            let mut line = format!(
+                "{} {}: {}/6",
+                reading.realm,
+                reading.region.name(),
+                reading.level
+            );
+            if reading.stale {
+                line.push_str(" (stale)");
+            }
+            if let Some(estimate) = &reading.estimate {
+                line = format!("{}, {}", line, estimate);
+            }
+            tooltip.push(line);
+        }
+
+        for (realm, e) in failed {
+            tooltip.push(format!("{}: failed to query progress: {}", realm, e));
+        }
+
+        // the first of the highest, so ties go to the first configured realm
+        let top = readings.iter().rev().max_by_key(|reading| reading.level);
+
+        match top {
+            Some(top) => Module {
+                text: format!("{} {}/6", top.region.name(), top.level),
+                short: format!("{}/6", top.level),
+                tooltip: tooltip.join("\n"),
+                level: Some(top.level),
+                stale: top.stale,
+            },
+            None if !failed.is_empty() => Module {
+                text: "DClone ?/6".to_owned(),
+                short: "?/6".to_owned(),
+                tooltip: tooltip.join("\n"),
+                level: None,
+                stale: false,
+            },
+            None => Module {
+                text: "DClone -/6".to_owned(),
+                short: "-/6".to_owned(),
+                tooltip: "No regions tracked".to_owned(),
+                level: None,
+                stale: false,
+            },
+        }
+    }
+
+    fn urgency(&self) -> Option<Urgency> {
+        self.level.and_then(Urgency::for_level)
+    }
+
+    /// Render the module, `watch` selecting the streaming variant of a format
+    fn render(&self, format: Format, watch: bool) -> String {
+        match format {
+            Format::Waybar => {
+                let mut class = vec![];
+                match (self.level, self.urgency()) {
+                    (Some(level), Some(urgency)) => {
+                        class.push(format!("level-{}", level));
+                        class.push(urgency.to_string());
+                    }
+                    (Some(level), None) => class.push(format!("level-{}", level)),
+                    (None, _) => class.push("error".to_owned()),
+                }
+                if self.stale {
+                    class.push("stale".to_owned());
+                }
+
+                json!({
+                    "text": self.text,
+                    "tooltip": self.tooltip,
+                    "class": class,
+                    "percentage": self.level.unwrap_or(0) * 100 / 6,
+                })
+                .to_string()
+            }
+            Format::Polybar => match self.urgency() {
+                Some(urgency) => format!("%{{F#{:06x}}}{}%{{F-}}", urgency.color(), self.text),
+                None => self.text.clone(),
+            },
+            // persistent blocks only take the full text, one line per update
+            Format::I3blocks if watch => self.text.clone(),
+            Format::I3blocks => {
+                let color = self
+                    .urgency()
+                    .map(|urgency| format!("#{:06x}", urgency.color()))
+                    .unwrap_or_default();
+                format!("{}\n{}\n{}", self.text, self.short, color)
+            }
+        }
+    }
+}
+
+/// Print the current progress for a status bar, once or on every update
+pub async fn run(config: Config, format: Format, watch: bool) -> Result<()> {
+    let client = Client::new(&config)?;
+    let history = tracker::read_history(&config);
+
+    if !watch {
+        let readings = fetch_readings(&client, &config, history.as_ref()).await;
+        println!("{}", Module::new(&readings).render(format, watch));
+        return Ok(());
+    }
+
+    let mut timer = tokio::time::interval(Duration::from_secs(config.interval));
+    loop {
+        timer.tick().await;
+
+        let readings = fetch_readings(&client, &config, history.as_ref()).await;
+        println!("{}", Module::new(&readings).render(format, watch));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::config::{self, Config};
+use crate::state;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, TryLockError};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long to wait for another process fetching the same URL, before fetching anyway
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to check whether the other process is done
+const LOCK_POLL: Duration = Duration::from_millis(100);
+
+/// Responses kept on disk, shared by every instance of the tracker
+pub struct Cache {
+    dir: PathBuf,
+    /// responses younger than this are used without asking the API
+    ttl: u64,
+}
+
+/// A cached response, along with what's needed to ask whether it changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// UNIX timestamp (seconds) of when the response was last confirmed
+    pub fetched_at: u64,
+    pub body: Value,
+}
+
+impl Entry {
+    pub fn new(etag: Option<String>, last_modified: Option<String>, body: Value) -> Self {
+        Entry {
+            etag,
+            last_modified,
+            fetched_at: state::now(),
+            body,
+        }
+    }
+}
+
+impl Cache {
+    pub fn new(config: &Config) -> Result<Self> {
+        let dir = match &config.cache_dir {
+            Some(dir) => dir.clone(),
+            None => default_dir()?,
+        };
+
+        Ok(Cache {
+            dir,
+            ttl: config.cache_ttl,
+        })
+    }
+
+    /// The cached response for `url`, if there is one
+    pub fn get(&self, url: &str) -> Option<Entry> {
+        let path = self.path(url, "json");
+        let contents = fs::read(&path).ok()?;
+        match serde_json::from_slice(&contents) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::warn!("Ignoring cached response {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Whether `entry` is recent enough to be used without asking the API
+    pub fn is_fresh(&self, entry: &Entry) -> bool {
+        state::now().saturating_sub(entry.fetched_at) < self.ttl
+    }
+
+    /// Store the response for `url`, logging any failure since the cache is optional
+    pub fn put(&self, url: &str, entry: &Entry) {
+        if let Err(e) = self.write(url, entry) {
+            log::warn!("Failed to cache response: {:#}", e);
+        }
+    }
+
+    fn write(&self, url: &str, entry: &Entry) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating {}", self.dir.display()))?;
+
+        // other instances may read the entry at any time, so replace it in one go
+        let path = self.path(url, "json");
+        let tmp = self.path(url, &format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_vec(entry)?)
+            .with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Wait until no other instance is fetching `url`, and keep them waiting until
+    /// the returned lock is dropped.
+    ///
+    /// If the lock can't be taken, this returns without it rather than not fetching.
+    pub async fn lock(&self, url: &str) -> Option<File> {
+        let path = self.path(url, "lock");
+        let file = fs::create_dir_all(&self.dir)
+            .and_then(|_| File::create(&path))
+            .map_err(|e| log::warn!("Failed to create {}: {}", path.display(), e))
+            .ok()?;
+
+        let start = tokio::time::Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Some(file),
+                Err(TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                    tokio::time::sleep(LOCK_POLL).await;
+                }
+                Err(TryLockError::WouldBlock) => {
+                    log::warn!("Timed out waiting for {}", path.display());
+                    return None;
+                }
+                Err(TryLockError::Error(e)) => {
+                    log::warn!("Failed to lock {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Where to keep things for `url`; the URL is hashed, since it may contain a token
+    fn path(&self, url: &str, extension: &str) -> PathBuf {
+        // a different Rust version may hash differently, which only costs a cache miss
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        self.dir
+            .join(format!("{:016x}.{}", hasher.finish(), extension))
+    }
+}
+
+/// Default location of the response cache, following the XDG base directory spec
+pub fn default_dir() -> Result<PathBuf> {
+    Ok(config::xdg_dir("XDG_CACHE_HOME", ".cache")?.join("responses"))
+}
@@ -0,0 +1,304 @@
+use crate::cache::{Cache, Entry};
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::source::SchemaError;
+use anyhow::Result;
+use reqwest::header::{
+    HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER,
+};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+use tokio::time::Instant;
+
+/// Least time between two requests to the API
+const MIN_SPACING: Duration = Duration::from_secs(2);
+
+/// How long a request may take before it counts as failed, well below the shortest
+/// interval so a hanging API can't hold up the tracker
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+const _: () = assert!(REQUEST_TIMEOUT.as_secs() < crate::config::MIN_INTERVAL);
+
+/// How long to back off after being rate limited without a `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// HTTP client shared by everything that queries an API, keeping to its rate limits
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// limits of each API, by host
+    limits: Arc<Mutex<HashMap<String, Limits>>>,
+    /// responses shared with other instances, if they can be kept on disk
+    cache: Option<Arc<Cache>>,
+    /// where to record the latency of requests, if anywhere
+    metrics: Option<Metrics>,
+}
+
+#[derive(Default)]
+struct Limits {
+    /// when the most recent request was, or is scheduled to be, sent
+    last_request: Option<Instant>,
+    /// no requests are sent before this, after the API asked to back off
+    blocked_until: Option<Instant>,
+}
+
+/// Why a query didn't return anything
+#[derive(Debug)]
+pub enum FetchError {
+    Http(reqwest::Error),
+    /// the API asked not to be queried again for a while
+    RateLimited(Duration),
+    /// the response isn't the JSON that was expected
+    Decode(serde_json::Error),
+    /// the response is JSON, but nothing in it can be tracked
+    Schema(SchemaError),
+}
+
+impl FetchError {
+    /// How long to wait before trying again, if the API said so
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            FetchError::RateLimited(delay) => Some(*delay),
+            FetchError::Http(_) | FetchError::Decode(_) | FetchError::Schema(_) => None,
+        }
+    }
+
+    /// Drop the URL from the error, for URLs that contain secrets
+    pub fn without_url(self) -> Self {
+        match self {
+            FetchError::Http(e) => FetchError::Http(e.without_url()),
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Http(e) => write!(f, "{}", e),
+            FetchError::RateLimited(delay) => {
+                write!(f, "rate limited, retry in {}s", delay.as_secs())
+            }
+            FetchError::Decode(e) => write!(f, "unexpected response: {}", e),
+            FetchError::Schema(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Http(e) => Some(e),
+            FetchError::RateLimited(_) => None,
+            FetchError::Decode(e) => Some(e),
+            FetchError::Schema(e) => Some(e),
+        }
+    }
+}
+
+impl From<SchemaError> for FetchError {
+    fn from(e: SchemaError) -> Self {
+        FetchError::Schema(e)
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Http(e)
+    }
+}
+
+impl Client {
+    pub fn new(config: &Config) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent("dclone-tracker/0.1.0 https://github.com/tronje/dclone-tracker")
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Client {
+            http,
+            limits: Arc::default(),
+            cache: open_cache(config),
+            metrics: None,
+        })
+    }
+
+    /// Record the latency of every request sent to an API in `metrics`
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Switch to the cache settings of `config`, keeping track of the rate limits
+    pub fn set_cache(&mut self, config: &Config) {
+        self.cache = open_cache(config);
+    }
+
+    /// The underlying client, for requests that aren't subject to the API's limits
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// Fetch a JSON response, once the rate limits allow it.
+    ///
+    /// A response cached in the last `cache_ttl` seconds is used as is, an older one
+    /// only if the API says it hasn't changed. HTTP errors are treated like any
+    /// other failure. While the API has asked to back off, no request is sent at all.
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        source: &str,
+        url: &str,
+    ) -> Result<T, FetchError> {
+        let body = match &self.cache {
+            Some(cache) => self.get_cached(cache, source, url).await?,
+            None => {
+                self.send(source, url, None)
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?
+            }
+        };
+
+        serde_json::from_value(body).map_err(FetchError::Decode)
+    }
+
+    async fn get_cached(
+        &self,
+        cache: &Cache,
+        source: &str,
+        url: &str,
+    ) -> Result<Value, FetchError> {
+        // another instance fetching the same URL will have cached it by the time we get the lock
+        let _lock = cache.lock(url).await;
+        let cached = cache.get(url);
+
+        if let Some(entry) = cached.as_ref().filter(|entry| cache.is_fresh(entry)) {
+            log::debug!("Using a response cached at {}", entry.fetched_at);
+            return Ok(entry.body.clone());
+        }
+
+        let response = self.send(source, url, cached.as_ref()).await?;
+        if let (StatusCode::NOT_MODIFIED, Some(entry)) = (response.status(), cached) {
+            log::debug!("Response hasn't changed since {}", entry.fetched_at);
+            let entry = Entry::new(entry.etag, entry.last_modified, entry.body);
+            cache.put(url, &entry);
+            return Ok(entry.body);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = header(&response, ETAG);
+        let last_modified = header(&response, LAST_MODIFIED);
+        let body: Value = response.json().await?;
+
+        cache.put(url, &Entry::new(etag, last_modified, body.clone()));
+
+        Ok(body)
+    }
+
+    /// Send a GET request to `source` once the rate limits allow it, asking only for
+    /// changes since `cached` if there's a cached response
+    async fn send(
+        &self,
+        source: &str,
+        url: &str,
+        cached: Option<&Entry>,
+    ) -> Result<reqwest::Response, FetchError> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_default();
+
+        let send_at = {
+            let mut limits = self.limits.lock().unwrap();
+            let limits = limits.entry(host.clone()).or_default();
+            let now = Instant::now();
+
+            if let Some(until) = limits.blocked_until {
+                if until > now {
+                    return Err(FetchError::RateLimited(until - now));
+                }
+                limits.blocked_until = None;
+            }
+
+            let send_at = match limits.last_request {
+                Some(last) => (last + MIN_SPACING).max(now),
+                None => now,
+            };
+            limits.last_request = Some(send_at);
+            send_at
+        };
+
+        tokio::time::sleep_until(send_at).await;
+
+        let mut request = self.http.get(url);
+        if let Some(entry) = cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        // only the request itself counts towards the latency, not waiting for the limits
+        let start = Instant::now();
+        let response = request.send().await;
+        if let Some(metrics) = &self.metrics {
+            metrics.request_sent(source, start.elapsed());
+        }
+
+        let response = response?;
+        let delay = retry_after(&response);
+        let status = response.status();
+
+        if status == StatusCode::TOO_MANY_REQUESTS
+            || (status == StatusCode::SERVICE_UNAVAILABLE && delay.is_some())
+        {
+            let delay = delay.unwrap_or(DEFAULT_RETRY_AFTER);
+            let mut limits = self.limits.lock().unwrap();
+            limits.entry(host).or_default().blocked_until = Some(Instant::now() + delay);
+            return Err(FetchError::RateLimited(delay));
+        }
+
+        Ok(response)
+    }
+}
+
+fn open_cache(config: &Config) -> Option<Arc<Cache>> {
+    if !config.cache {
+        return None;
+    }
+
+    match Cache::new(config) {
+        Ok(cache) => Some(Arc::new(cache)),
+        Err(e) => {
+            log::warn!("Responses are not cached: {:#}", e);
+            None
+        }
+    }
+}
+
+fn header(response: &reqwest::Response, name: HeaderName) -> Option<String> {
+    let value = response.headers().get(name)?.to_str().ok()?;
+    Some(value.to_owned())
+}
+
+/// The `Retry-After` header, given either in seconds or as an HTTP date
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = OffsetDateTime::parse(value, &Rfc2822).ok()?;
+    let secs = (date - OffsetDateTime::now_utc()).whole_seconds();
+    Some(Duration::from_secs(secs.max(0) as u64))
+}
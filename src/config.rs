@@ -0,0 +1,635 @@
+use crate::notifier::{Mention, Template};
+use crate::rules::Rule;
+use crate::source::{self, d2runewizard, diablo2io};
+use crate::{Realm, Region};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Shortest query interval (seconds), diablo2.io asks not to be polled more often
+pub const MIN_INTERVAL: u64 = 60;
+
+/// Tracker configuration, as read from the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// query interval (seconds)
+    pub interval: u64,
+
+    /// file to persist the tracker state in
+    pub state_file: Option<PathBuf>,
+
+    /// discard persisted state older than this (minutes)
+    pub max_state_age: u64,
+
+    /// record the history of observed progress
+    pub history: bool,
+
+    /// database to record the history in
+    pub history_file: Option<PathBuf>,
+
+    /// address to serve the status API on
+    pub listen: Option<SocketAddr>,
+
+    /// cache responses on disk, shared with other instances
+    pub cache: bool,
+
+    /// use cached responses younger than this (seconds) without querying the API
+    pub cache_ttl: u64,
+
+    /// directory to cache responses in
+    pub cache_dir: Option<PathBuf>,
+
+    /// how often a failed query is retried, with backoff, before waiting a full interval
+    pub fetch_retries: u32,
+
+    /// consecutive failed queries of a realm before notifying that tracking is degraded
+    pub degraded_after: u32,
+
+    /// when the sources of a realm disagree, how old the most recent report may be
+    /// (seconds) to be trusted anyway
+    pub max_report_age: u64,
+
+    /// how long (seconds) a region can go without a new report before its level
+    /// is considered stale
+    pub stale_after: u64,
+
+    /// what to do with notifications that are based on stale reports
+    pub stale_notifications: StalePolicy,
+
+    #[serde(rename = "source")]
+    pub sources: SourcesConfig,
+
+    #[serde(rename = "realm")]
+    pub realms: Vec<RealmConfig>,
+
+    #[serde(rename = "notifier")]
+    pub notifiers: Vec<NotifierConfig>,
+
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            interval: 90,
+            state_file: None,
+            max_state_age: 60,
+            history: true,
+            history_file: None,
+            listen: None,
+            cache: true,
+            cache_ttl: 30,
+            cache_dir: None,
+            fetch_retries: 3,
+            degraded_after: 3,
+            max_report_age: 300,
+            stale_after: 3600,
+            stale_notifications: StalePolicy::default(),
+            sources: SourcesConfig::default(),
+            realms: Vec::new(),
+            notifiers: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// How to notify about changes that are based on stale reports
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StalePolicy {
+    /// notify as usual
+    #[default]
+    Send,
+    /// notify with low urgency, mentioning how old the report is
+    Soften,
+    /// don't notify at all
+    Suppress,
+}
+
+/// Settings of the sources progress is queried from
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcesConfig {
+    pub diablo2io: Diablo2ioConfig,
+    pub d2runewizard: D2runewizardConfig,
+
+    #[serde(rename = "mirror")]
+    pub mirrors: Vec<MirrorConfig>,
+}
+
+impl SourcesConfig {
+    /// API format of the source called `name`, if there is one
+    pub fn kind(&self, name: &str) -> Option<source::Kind> {
+        name.parse().ok().or_else(|| {
+            self.mirrors
+                .iter()
+                .find(|mirror| mirror.name == name)
+                .map(|mirror| mirror.format)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Diablo2ioConfig {
+    /// endpoint of the API, to use a mirror or a local stand-in
+    pub url: String,
+}
+
+impl Default for Diablo2ioConfig {
+    fn default() -> Self {
+        Diablo2ioConfig {
+            url: diablo2io::DEFAULT_URL.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct D2runewizardConfig {
+    /// endpoint of the API
+    pub url: String,
+
+    /// API token, see https://d2runewizard.com/integration
+    pub token: Option<String>,
+}
+
+// the token is a secret, keep it out of logs
+impl fmt::Debug for D2runewizardConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("D2runewizardConfig")
+            .field("url", &self.url)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Default for D2runewizardConfig {
+    fn default() -> Self {
+        D2runewizardConfig {
+            url: d2runewizard::DEFAULT_URL.to_owned(),
+            token: None,
+        }
+    }
+}
+
+/// Another endpoint serving the format of one of the built-in sources
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// name to refer to the mirror by in realms
+    pub name: String,
+
+    /// API format the mirror serves
+    #[serde(default)]
+    pub format: source::Kind,
+
+    pub url: String,
+
+    /// API token, if the format needs one
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for MirrorConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MirrorConfig")
+            .field("name", &self.name)
+            .field("format", &self.format)
+            .field("url", &self.url)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// A realm to track, which of its regions and where to query them
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RealmConfig {
+    #[serde(rename = "name")]
+    pub realm: Realm,
+
+    #[serde(default = "all_regions")]
+    pub regions: Vec<Region>,
+
+    /// names of the sources to query the realm's progress from, cross-checked
+    /// against each other if there are several
+    #[serde(
+        rename = "source",
+        default = "default_sources",
+        deserialize_with = "one_or_many"
+    )]
+    pub sources: Vec<String>,
+}
+
+impl RealmConfig {
+    pub fn new(realm: Realm) -> Self {
+        RealmConfig {
+            realm,
+            regions: all_regions(),
+            sources: default_sources(),
+        }
+    }
+}
+
+fn all_regions() -> Vec<Region> {
+    Region::ALL.to_vec()
+}
+
+fn default_sources() -> Vec<String> {
+    vec![source::Kind::default().to_string()]
+}
+
+/// A single name or a list of them
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    })
+}
+
+/// A notification backend and its settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum NotifierConfig {
+    Desktop,
+    Stdout,
+    Exec {
+        command: String,
+    },
+    Webhook {
+        urls: Vec<String>,
+        template: Option<serde_json::Value>,
+        #[serde(default = "default_retries")]
+        retries: u32,
+        #[serde(default = "default_timeout")]
+        timeout: u64,
+    },
+    Discord {
+        urls: Vec<String>,
+        #[serde(default)]
+        mentions: Vec<Mention>,
+        #[serde(default = "default_retries")]
+        retries: u32,
+        #[serde(default = "default_timeout")]
+        timeout: u64,
+    },
+    Slack {
+        urls: Vec<String>,
+        #[serde(default)]
+        mentions: Vec<Mention>,
+        #[serde(default = "default_retries")]
+        retries: u32,
+        #[serde(default = "default_timeout")]
+        timeout: u64,
+    },
+}
+
+pub fn default_retries() -> u32 {
+    3
+}
+
+pub fn default_timeout() -> u64 {
+    10
+}
+
+impl Config {
+    /// Read and validate the config file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let config: Config =
+            toml::from_str(&contents).with_context(|| format!("in {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("in {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Check the settings that can't be expressed through types alone
+    pub fn validate(&self) -> Result<()> {
+        if self.interval < MIN_INTERVAL {
+            return Err(anyhow!(
+                "interval: must be at least {} seconds",
+                MIN_INTERVAL
+            ));
+        }
+
+        if self.degraded_after == 0 {
+            return Err(anyhow!("degraded_after: must be at least 1"));
+        }
+
+        if self.stale_after == 0 {
+            return Err(anyhow!("stale_after: must be at least 1"));
+        }
+
+        reqwest::Url::parse(&self.sources.diablo2io.url)
+            .map_err(|e| anyhow!("source.diablo2io.url: {}", e))?;
+        reqwest::Url::parse(&self.sources.d2runewizard.url)
+            .map_err(|e| anyhow!("source.d2runewizard.url: {}", e))?;
+
+        for (i, mirror) in self.sources.mirrors.iter().enumerate() {
+            if mirror.name.trim().is_empty() {
+                return Err(anyhow!("source.mirror[{}].name: must not be empty", i));
+            }
+
+            if mirror.name.parse::<source::Kind>().is_ok() {
+                return Err(anyhow!(
+                    "source.mirror[{}].name: `{}` is the name of a built-in source",
+                    i,
+                    mirror.name
+                ));
+            }
+
+            if self.sources.mirrors[..i]
+                .iter()
+                .any(|m| m.name == mirror.name)
+            {
+                return Err(anyhow!(
+                    "source.mirror[{}].name: `{}` is configured more than once",
+                    i,
+                    mirror.name
+                ));
+            }
+
+            reqwest::Url::parse(&mirror.url)
+                .map_err(|e| anyhow!("source.mirror[{}].url: {}", i, e))?;
+        }
+
+        for (i, realm) in self.realms.iter().enumerate() {
+            if self.realms[..i].iter().any(|r| r.realm == realm.realm) {
+                return Err(anyhow!(
+                    "realm[{}].name: `{}` is configured more than once",
+                    i,
+                    realm.realm.id()
+                ));
+            }
+
+            if realm.regions.is_empty() {
+                return Err(anyhow!("realm[{}].regions: must not be empty", i));
+            }
+
+            if realm.sources.is_empty() {
+                return Err(anyhow!("realm[{}].source: must not be empty", i));
+            }
+
+            for (j, name) in realm.sources.iter().enumerate() {
+                if self.sources.kind(name).is_none() {
+                    return Err(anyhow!(
+                        "realm[{}].source: unknown source `{}`, expected diablo2io, d2runewizard or the name of a mirror",
+                        i,
+                        name
+                    ));
+                }
+
+                if realm.sources[..j].contains(name) {
+                    return Err(anyhow!(
+                        "realm[{}].source: `{}` is listed more than once",
+                        i,
+                        name
+                    ));
+                }
+            }
+        }
+
+        for (i, notifier) in self.notifiers.iter().enumerate() {
+            match notifier {
+                NotifierConfig::Desktop | NotifierConfig::Stdout => {}
+                NotifierConfig::Exec { command } => {
+                    if command.trim().is_empty() {
+                        return Err(anyhow!("notifier[{}].command: must not be empty", i));
+                    }
+                }
+                NotifierConfig::Webhook { urls, template, .. } => {
+                    check_urls(i, urls)?;
+                    if let Some(template) = template {
+                        Template::new(template.clone())
+                            .map_err(|e| anyhow!("notifier[{}].template: {}", i, e))?;
+                    }
+                }
+                NotifierConfig::Discord { urls, .. } | NotifierConfig::Slack { urls, .. } => {
+                    check_urls(i, urls)?;
+                }
+            }
+        }
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !(1..=6).contains(&rule.min_level) {
+                return Err(anyhow!("rule[{}].min_level: must be between 1 and 6", i));
+            }
+
+            if rule.realms.as_ref().is_some_and(Vec::is_empty) {
+                return Err(anyhow!("rule[{}].realms: must not be empty", i));
+            }
+
+            if rule.regions.as_ref().is_some_and(Vec::is_empty) {
+                return Err(anyhow!("rule[{}].regions: must not be empty", i));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Describe what changed between `self` and `new`, one line per change
+    pub fn diff(&self, new: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.interval != new.interval {
+            changes.push(format!("interval: {}s -> {}s", self.interval, new.interval));
+        }
+
+        if self.state_file != new.state_file {
+            changes.push(format!(
+                "state_file: {:?} -> {:?}",
+                self.state_file, new.state_file
+            ));
+        }
+
+        if self.max_state_age != new.max_state_age {
+            changes.push(format!(
+                "max_state_age: {}min -> {}min",
+                self.max_state_age, new.max_state_age
+            ));
+        }
+
+        if self.history != new.history || self.history_file != new.history_file {
+            changes.push(format!(
+                "history: {:?} -> {:?}",
+                self.history.then_some(&self.history_file),
+                new.history.then_some(&new.history_file)
+            ));
+        }
+
+        if self.listen != new.listen {
+            changes.push(format!("listen: {:?} -> {:?}", self.listen, new.listen));
+        }
+
+        if self.cache_ttl != new.cache_ttl {
+            changes.push(format!(
+                "cache_ttl: {}s -> {}s",
+                self.cache_ttl, new.cache_ttl
+            ));
+        }
+
+        if self.cache != new.cache || self.cache_dir != new.cache_dir {
+            changes.push(format!(
+                "cache: {:?} -> {:?}",
+                self.cache.then_some(&self.cache_dir),
+                new.cache.then_some(&new.cache_dir)
+            ));
+        }
+
+        if self.sources != new.sources {
+            changes.push(format!("source: {:?} -> {:?}", self.sources, new.sources));
+        }
+
+        if self.fetch_retries != new.fetch_retries {
+            changes.push(format!(
+                "fetch_retries: {} -> {}",
+                self.fetch_retries, new.fetch_retries
+            ));
+        }
+
+        if self.degraded_after != new.degraded_after {
+            changes.push(format!(
+                "degraded_after: {} -> {}",
+                self.degraded_after, new.degraded_after
+            ));
+        }
+
+        if self.stale_after != new.stale_after {
+            changes.push(format!(
+                "stale_after: {}s -> {}s",
+                self.stale_after, new.stale_after
+            ));
+        }
+
+        if self.stale_notifications != new.stale_notifications {
+            changes.push(format!(
+                "stale_notifications: {:?} -> {:?}",
+                self.stale_notifications, new.stale_notifications
+            ));
+        }
+
+        if self.max_report_age != new.max_report_age {
+            changes.push(format!(
+                "max_report_age: {}s -> {}s",
+                self.max_report_age, new.max_report_age
+            ));
+        }
+
+        for old in &self.realms {
+            match new.realms.iter().find(|r| r.realm == old.realm) {
+                None => changes.push(format!("realm removed: {}", old.realm)),
+                Some(r) => {
+                    if r.regions != old.regions {
+                        changes.push(format!(
+                            "realm {}: regions {:?} -> {:?}",
+                            old.realm, old.regions, r.regions
+                        ));
+                    }
+
+                    if r.sources != old.sources {
+                        changes.push(format!(
+                            "realm {}: source {:?} -> {:?}",
+                            old.realm, old.sources, r.sources
+                        ));
+                    }
+                }
+            }
+        }
+
+        for realm in &new.realms {
+            if !self.realms.iter().any(|r| r.realm == realm.realm) {
+                changes.push(format!("realm added: {} {:?}", realm.realm, realm.regions));
+            }
+        }
+
+        for old in &self.notifiers {
+            if !new.notifiers.contains(old) {
+                changes.push(format!("notifier removed: {}", old.describe()));
+            }
+        }
+
+        for notifier in &new.notifiers {
+            if !self.notifiers.contains(notifier) {
+                changes.push(format!("notifier added: {}", notifier.describe()));
+            }
+        }
+
+        if self.rules != new.rules {
+            changes.push(format!(
+                "rules: {} -> {} rules",
+                self.rules.len(),
+                new.rules.len()
+            ));
+        }
+
+        changes
+    }
+}
+
+impl NotifierConfig {
+    /// Short description for log messages, leaving out credentials and URLs
+    pub fn describe(&self) -> String {
+        match self {
+            NotifierConfig::Desktop => "desktop".to_owned(),
+            NotifierConfig::Stdout => "stdout".to_owned(),
+            NotifierConfig::Exec { command } => format!("exec `{}`", command),
+            NotifierConfig::Webhook { urls, .. } => format!("webhook ({} URLs)", urls.len()),
+            NotifierConfig::Discord { urls, .. } => format!("discord ({} URLs)", urls.len()),
+            NotifierConfig::Slack { urls, .. } => format!("slack ({} URLs)", urls.len()),
+        }
+    }
+}
+
+fn check_urls(i: usize, urls: &[String]) -> Result<()> {
+    if urls.is_empty() {
+        return Err(anyhow!("notifier[{}].urls: must not be empty", i));
+    }
+
+    for (j, url) in urls.iter().enumerate() {
+        reqwest::Url::parse(url).map_err(|e| anyhow!("notifier[{}].urls[{}]: {}", i, j, e))?;
+    }
+
+    Ok(())
+}
+
+/// Default location of the config file, following the XDG base directory spec
+pub fn default_path() -> Option<PathBuf> {
+    Some(
+        xdg_dir("XDG_CONFIG_HOME", ".config")
+            .ok()?
+            .join("config.toml"),
+    )
+}
+
+/// The tracker's directory in the XDG base directory `var`, which defaults to
+/// `fallback` in the home directory
+pub fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+    let dir = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("$HOME is not set"))?;
+            PathBuf::from(home).join(fallback)
+        }
+    };
+
+    Ok(dir.join("dclone-tracker"))
+}
@@ -0,0 +1,234 @@
+use crate::history::History;
+use crate::{Realm, Region};
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+
+/// How many of the most recent durations of a level are considered
+const SAMPLES: usize = 50;
+
+/// How much an estimate can be relied on
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    fn from_samples(samples: usize) -> Self {
+        match samples {
+            0..=2 => Confidence::Low,
+            3..=9 => Confidence::Medium,
+            _ => Confidence::High,
+        }
+    }
+
+    fn lower(self) -> Self {
+        match self {
+            Confidence::High => Confidence::Medium,
+            _ => Confidence::Low,
+        }
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Estimated time until DClone walks in a region
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize)]
+pub struct Estimate {
+    /// seconds until level 6 is reached
+    pub seconds: u64,
+    pub confidence: Confidence,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = self.seconds.div_ceil(60);
+        let duration = if minutes < 60 {
+            format!("~{} min", minutes)
+        } else if minutes < 48 * 60 {
+            format!("~{} h {} min", minutes / 60, minutes % 60)
+        } else {
+            format!("~{} days", minutes / (24 * 60))
+        };
+
+        write!(
+            f,
+            "est. walk in {}, {} confidence",
+            duration, self.confidence
+        )
+    }
+}
+
+/// Estimate when a region at `level` reaches 6, from how long each level took in the past.
+///
+/// `elapsed` is how long the region has been at `level` already, if known.
+pub fn estimate(
+    history: &History,
+    realm: Realm,
+    region: Region,
+    level: i32,
+    elapsed: Option<u64>,
+) -> Result<Option<Estimate>> {
+    if !(1..6).contains(&level) {
+        return Ok(None);
+    }
+
+    let mut seconds = 0;
+    let mut samples = usize::MAX;
+
+    for l in level..6 {
+        let durations = history.stage_durations(realm, l, SAMPLES)?;
+        samples = samples.min(durations.len());
+
+        let median = match median(durations) {
+            Some(median) => median,
+            None => return Ok(None),
+        };
+
+        seconds += if l == level {
+            median.saturating_sub(elapsed.unwrap_or(0))
+        } else {
+            median
+        };
+    }
+
+    let mut confidence = Confidence::from_samples(samples);
+    if elapsed.is_none() {
+        confidence = confidence.lower();
+    }
+
+    log::debug!(
+        "[{}] {} at {}: {}s until walk from {} samples",
+        realm,
+        region,
+        level,
+        seconds,
+        samples
+    );
+
+    Ok(Some(Estimate {
+        seconds,
+        confidence,
+    }))
+}
+
+fn median(mut values: Vec<u64>) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2)
+    } else {
+        Some(values[mid])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn history(stages: &[(i32, &[u64])]) -> History {
+        let history = History::open(Path::new(":memory:")).unwrap();
+        let mut ended_at = 1_000_000;
+        for &(level, durations) in stages {
+            for &duration in durations {
+                ended_at += 10;
+                history
+                    .record_stage(
+                        Realm::default(),
+                        Region::Europe,
+                        level,
+                        ended_at - duration,
+                        ended_at,
+                    )
+                    .unwrap();
+            }
+        }
+        history
+    }
+
+    fn estimate(history: &History, level: i32, elapsed: Option<u64>) -> Option<Estimate> {
+        super::estimate(history, Realm::default(), Region::Asia, level, elapsed).unwrap()
+    }
+
+    #[test]
+    fn median() {
+        assert_eq!(super::median(vec![]), None);
+        assert_eq!(super::median(vec![7]), Some(7));
+        assert_eq!(super::median(vec![300, 100, 200]), Some(200));
+        assert_eq!(super::median(vec![400, 100, 300, 200]), Some(250));
+    }
+
+    #[test]
+    fn adds_up_the_remaining_levels() {
+        let history = history(&[(4, &[1000, 3000]), (5, &[100, 300, 200])]);
+
+        assert_eq!(
+            estimate(&history, 5, Some(50)),
+            Some(Estimate {
+                seconds: 150,
+                confidence: Confidence::Medium,
+            })
+        );
+        assert_eq!(
+            estimate(&history, 4, Some(0)),
+            Some(Estimate {
+                seconds: 2200,
+                confidence: Confidence::Low,
+            })
+        );
+    }
+
+    #[test]
+    fn missing_levels() {
+        let history = history(&[(3, &[600]), (5, &[100])]);
+        assert_eq!(estimate(&history, 3, Some(0)), None);
+        assert_eq!(estimate(&history, 4, Some(0)), None);
+
+        // there's nothing left to estimate from level 6, or before level 1
+        assert_eq!(estimate(&history, 6, Some(0)), None);
+        assert_eq!(estimate(&history, 0, Some(0)), None);
+    }
+
+    #[test]
+    fn elapsed_beyond_the_median() {
+        let history = history(&[(4, &[1000]), (5, &[100])]);
+        assert_eq!(estimate(&history, 5, Some(500)).unwrap().seconds, 0);
+        assert_eq!(estimate(&history, 4, Some(5000)).unwrap().seconds, 100);
+    }
+
+    #[test]
+    fn unknown_elapsed_lowers_confidence() {
+        let many = history(&[(5, &[100; 10])]);
+        assert_eq!(
+            estimate(&many, 5, Some(0)).unwrap().confidence,
+            Confidence::High
+        );
+        assert_eq!(
+            estimate(&many, 5, None),
+            Some(Estimate {
+                seconds: 100,
+                confidence: Confidence::Medium,
+            })
+        );
+
+        let few = history(&[(5, &[100])]);
+        assert_eq!(estimate(&few, 5, None).unwrap().confidence, Confidence::Low);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::config;
+use crate::notifier::Event;
+use crate::{Realm, Region};
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, params_from_iter, Connection, OpenFlags};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// A local database of everything the tracker has seen
+pub struct History {
+    conn: Connection,
+}
+
+/// A single level reported for a region
+#[derive(Debug, Clone, Copy)]
+pub struct Observation {
+    pub realm: Realm,
+    pub region: Region,
+    pub level: i32,
+    /// UNIX timestamp (seconds) of when the API last received a report, if it said
+    pub reported_at: Option<u64>,
+}
+
+/// Which records to return from a query
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub realms: Vec<Realm>,
+    pub regions: Vec<Region>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+/// A row of the history, either an observation or a change
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub realm: String,
+    pub region: String,
+    /// `observation`, `change` or `walk`
+    pub kind: String,
+    pub old: Option<i32>,
+    pub level: i32,
+    pub fetched_at: u64,
+    pub reported_at: Option<u64>,
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS observations (
+        id          INTEGER PRIMARY KEY,
+        realm       TEXT NOT NULL,
+        region      TEXT NOT NULL,
+        level       INTEGER NOT NULL,
+        fetched_at  INTEGER NOT NULL,
+        reported_at INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS observations_time ON observations (fetched_at);
+
+    CREATE TABLE IF NOT EXISTS changes (
+        id          INTEGER PRIMARY KEY,
+        realm       TEXT NOT NULL,
+        region      TEXT NOT NULL,
+        kind        TEXT NOT NULL,
+        old         INTEGER NOT NULL,
+        new         INTEGER NOT NULL,
+        fetched_at  INTEGER NOT NULL,
+        reported_at INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS changes_time ON changes (fetched_at);
+
+    CREATE TABLE IF NOT EXISTS stages (
+        id          INTEGER PRIMARY KEY,
+        realm       TEXT NOT NULL,
+        region      TEXT NOT NULL,
+        level       INTEGER NOT NULL,
+        started_at  INTEGER NOT NULL,
+        ended_at    INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS stages_level ON stages (realm, level);
+";
+
+impl History {
+    /// Open the database at `path`, creating it if necessary
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+
+        let conn = Connection::open(path).with_context(|| format!("opening {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("setting up {}", path.display()))?;
+
+        Ok(History { conn })
+    }
+
+    /// Open the existing database at `path` for reading only, leaving it as it is
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let conn = Connection::open_with_flags(path, flags)
+            .with_context(|| format!("opening {}", path.display()))?;
+
+        Ok(History { conn })
+    }
+
+    /// Record the levels of one fetch
+    pub fn record_observations(
+        &mut self,
+        fetched_at: u64,
+        observations: &[Observation],
+    ) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO observations (realm, region, level, fetched_at, reported_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for o in observations {
+                stmt.execute(params![
+                    o.realm.id(),
+                    String::from(o.region),
+                    o.level,
+                    fetched_at,
+                    o.reported_at,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Record a change in progress
+    pub fn record_change(&self, event: &Event, reported_at: Option<u64>) -> Result<()> {
+        let region = event
+            .region
+            .ok_or_else(|| anyhow!("only changes in progress are recorded"))?;
+        let fetched_at = event
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        self.conn.execute(
+            "INSERT INTO changes (realm, region, kind, old, new, fetched_at, reported_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                event.realm.id(),
+                String::from(region),
+                event.kind.to_string(),
+                event.old,
+                event.new,
+                fetched_at,
+                reported_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// When `region` reached `level`, if that change was seen.
+    ///
+    /// Only the most recent change counts, and a change from the unknown level 0
+    /// doesn't tell when the level was actually reached.
+    pub fn level_started(&self, realm: Realm, region: Region, level: i32) -> Result<Option<u64>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT old, new, fetched_at FROM changes
+             WHERE realm = ?1 AND region = ?2
+             ORDER BY fetched_at DESC, id DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query(params![realm.id(), String::from(region)])?;
+
+        match rows.next()? {
+            Some(row) => {
+                let (old, new): (i32, i32) = (row.get(0)?, row.get(1)?);
+                if old != 0 && new == level {
+                    Ok(Some(row.get(2)?))
+                } else {
+                    Ok(None)
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record how long a region stayed at a level
+    pub fn record_stage(
+        &self,
+        realm: Realm,
+        region: Region,
+        level: i32,
+        started_at: u64,
+        ended_at: u64,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO stages (realm, region, level, started_at, ended_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                realm.id(),
+                String::from(region),
+                level,
+                started_at,
+                ended_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recent durations (seconds) of `level` in any region of `realm`
+    pub fn stage_durations(&self, realm: Realm, level: i32, limit: usize) -> Result<Vec<u64>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT ended_at - started_at FROM stages
+             WHERE realm = ?1 AND level = ?2
+             ORDER BY ended_at DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![realm.id(), level, limit as i64], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Query the recorded changes, or all observations, oldest first
+    pub fn query(&self, filter: &Filter, observations: bool) -> Result<Vec<Record>> {
+        let mut sql = if observations {
+            String::from(
+                "SELECT realm, region, 'observation', NULL, level, fetched_at, reported_at
+                 FROM observations WHERE 1 = 1",
+            )
+        } else {
+            String::from(
+                "SELECT realm, region, kind, old, new, fetched_at, reported_at
+                 FROM changes WHERE 1 = 1",
+            )
+        };
+        let mut args: Vec<rusqlite::types::Value> = Vec::new();
+
+        if !filter.realms.is_empty() {
+            sql.push_str(&format!(
+                " AND realm IN ({})",
+                placeholders(filter.realms.len())
+            ));
+            args.extend(filter.realms.iter().map(|r| r.id().to_owned().into()));
+        }
+
+        if !filter.regions.is_empty() {
+            sql.push_str(&format!(
+                " AND region IN ({})",
+                placeholders(filter.regions.len())
+            ));
+            args.extend(filter.regions.iter().map(|&r| String::from(r).into()));
+        }
+
+        if let Some(since) = filter.since {
+            sql.push_str(" AND fetched_at >= ?");
+            args.push((since as i64).into());
+        }
+
+        if let Some(until) = filter.until {
+            sql.push_str(" AND fetched_at <= ?");
+            args.push((until as i64).into());
+        }
+
+        // with a limit, the most recent records are the interesting ones
+        sql.push_str(" ORDER BY fetched_at DESC, id DESC");
+        if let Some(limit) = filter.limit {
+            sql.push_str(" LIMIT ?");
+            args.push(i64::from(limit).into());
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(args), |row| {
+            Ok(Record {
+                realm: row.get(0)?,
+                region: row.get(1)?,
+                kind: row.get(2)?,
+                old: row.get(3)?,
+                level: row.get(4)?,
+                fetched_at: row.get(5)?,
+                reported_at: row.get(6)?,
+            })
+        })?;
+
+        let mut records = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        records.reverse();
+        Ok(records)
+    }
+}
+
+fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
+}
+
+/// Default location of the database, following the XDG base directory spec
+pub fn default_path() -> Result<PathBuf> {
+    Ok(config::xdg_dir("XDG_DATA_HOME", ".local/share")?.join("history.sqlite"))
+}
+
+/// Format a UNIX timestamp as RFC 3339 date
+pub fn format_time(secs: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(secs as i64)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_else(|| secs.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn change(history: &History, region: Region, old: i32, new: i32, at: u64) {
+        let mut event = Event::new(Realm::default(), region, old, new).unwrap();
+        event.time = UNIX_EPOCH + Duration::from_secs(at);
+        history.record_change(&event, None).unwrap();
+    }
+
+    #[test]
+    fn level_started() {
+        let history = History::open(Path::new(":memory:")).unwrap();
+        let realm = Realm::default();
+        let started = |region, level| history.level_started(realm, region, level).unwrap();
+
+        assert_eq!(started(Region::Europe, 3), None);
+
+        change(&history, Region::Europe, 2, 3, 100);
+        change(&history, Region::Asia, 3, 4, 200);
+        assert_eq!(started(Region::Europe, 3), Some(100));
+        assert_eq!(started(Region::Europe, 2), None);
+        assert_eq!(started(Region::Asia, 4), Some(200));
+
+        let other = Realm {
+            ladder: false,
+            hardcore: true,
+        };
+        assert_eq!(
+            history.level_started(other, Region::Europe, 3).unwrap(),
+            None
+        );
+
+        // only the most recent change counts
+        change(&history, Region::Europe, 3, 4, 300);
+        assert_eq!(started(Region::Europe, 3), None);
+        assert_eq!(started(Region::Europe, 4), Some(300));
+
+        // the level a region was first seen at may have started long before
+        change(&history, Region::Americas, 0, 5, 400);
+        assert_eq!(started(Region::Americas, 5), None);
+    }
+}
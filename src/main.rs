@@ -1,19 +1,65 @@
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Context, Result};
 use argh::FromArgs;
-use libnotify::Urgency;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use simple_logger::SimpleLogger;
 use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
 use tokio::select;
 use tokio::signal::unix::SignalKind;
 
-/// Get notified by libnotify whenever DClone status changes
+mod bar;
+mod cache;
+mod client;
+mod config;
+mod eta;
+mod history;
+mod metrics;
+mod notifier;
+mod output;
+mod rules;
+mod server;
+mod source;
+mod state;
+mod tracker;
+mod tui;
+
+use client::{Client, FetchError};
+use config::{Config, NotifierConfig, RealmConfig};
+use history::History;
+use notifier::Event;
+use rules::Rule;
+use tokio::sync::{broadcast, watch};
+use tracker::Tracker;
+
+/// Get notified whenever DClone status changes
 #[derive(Debug, FromArgs)]
 struct Opts {
-    /// query interval (seconds)
-    #[argh(option, default = "90")]
-    interval: u64,
+    /// config file
+    /// (default: $XDG_CONFIG_HOME/dclone-tracker/config.toml)
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// query interval (seconds, default: 90, at least 60)
+    #[argh(option)]
+    interval: Option<u64>,
+
+    /// endpoint of the diablo2.io API, to use a mirror or a local stand-in
+    /// (default: https://diablo2.io/dclone_api.php)
+    #[argh(option)]
+    diablo2io_url: Option<String>,
+
+    /// where to query all realms: diablo2io (default), d2runewizard or the name
+    /// of a mirror; may be repeated to cross-check several sources
+    #[argh(option)]
+    source: Vec<String>,
+
+    /// token for the d2runewizard API
+    #[argh(option)]
+    d2runewizard_token: Option<String>,
 
     /// ladder realm (by default, non-ladder is queried)
     #[argh(switch)]
@@ -23,37 +69,554 @@ struct Opts {
     #[argh(switch)]
     hardcore: bool,
 
+    /// realm to query, e.g. `sc-ladder` or `hc-nonladder`; may be repeated
+    /// (overrides --ladder and --hardcore)
+    #[argh(option)]
+    realm: Vec<Realm>,
+
+    /// query all realms
+    #[argh(switch)]
+    all_realms: bool,
+
+    /// region to track (americas, europe or asia); may be repeated
+    /// (by default, all regions are tracked)
+    #[argh(option)]
+    region: Vec<Region>,
+
     /// don't monitor, just query the state once
     #[argh(switch)]
     oneshot: bool,
+
+    /// with --oneshot, write the results to stdout as json, csv, table or plain
+    /// instead of logging them
+    #[argh(option)]
+    format: Option<output::Format>,
+
+    /// with --oneshot, exit with 0 if any region is at or above this level,
+    /// 1 if none is and 2 if not every realm could be queried
+    #[argh(option)]
+    threshold: Option<i32>,
+
+    /// file to persist the tracker state in
+    /// (default: $XDG_STATE_HOME/dclone-tracker/state.json)
+    #[argh(option)]
+    state_file: Option<PathBuf>,
+
+    /// discard persisted state older than this (minutes, default: 60)
+    #[argh(option)]
+    max_state_age: Option<u64>,
+
+    /// notification backend (desktop or stdout); may be repeated
+    /// (default: desktop)
+    #[argh(option)]
+    notifier: Vec<notifier::Kind>,
+
+    /// shell command to run on every status change; may be repeated
+    /// (the change is passed in DCLONE_* environment variables)
+    #[argh(option)]
+    exec: Vec<String>,
+
+    /// URL to POST status changes to as JSON; may be repeated
+    #[argh(option)]
+    webhook: Vec<String>,
+
+    /// JSON body to send to webhooks, with `{{field}}` placeholders for
+    /// realm, region, kind, old, new, timestamp, urgency, stale, title and message
+    #[argh(option)]
+    webhook_template: Option<String>,
+
+    /// webhook URL of a Discord channel to post status changes to; may be repeated
+    #[argh(option)]
+    discord_webhook: Vec<String>,
+
+    /// role or user to mention on Discord at levels 5 and 6, as
+    /// `role:<id>` or `user:<id>`; may be repeated
+    #[argh(option)]
+    discord_mention: Vec<notifier::Mention>,
+
+    /// webhook URL of a Slack channel to post status changes to; may be repeated
+    #[argh(option)]
+    slack_webhook: Vec<String>,
+
+    /// user group or user to mention on Slack at levels 5 and 6, as
+    /// `role:<id>` or `user:<id>`; may be repeated
+    #[argh(option)]
+    slack_mention: Vec<notifier::Mention>,
+
+    /// only notify about levels at or above this
+    /// (overrides the rules of the config file)
+    #[argh(option)]
+    min_level: Option<i32>,
+
+    /// only notify when a level goes up
+    /// (overrides the rules of the config file)
+    #[argh(switch)]
+    rising_only: bool,
+
+    /// database to record the history of observed progress in
+    /// (default: $XDG_DATA_HOME/dclone-tracker/history.sqlite)
+    #[argh(option)]
+    history_file: Option<PathBuf>,
+
+    /// don't record any history
+    #[argh(switch)]
+    no_history: bool,
+
+    /// don't cache responses on disk
+    #[argh(switch)]
+    no_cache: bool,
+
+    /// address to serve the status API on, e.g. `127.0.0.1:8080`
+    #[argh(option)]
+    listen: Option<SocketAddr>,
+
+    /// how often to retry a failed webhook request (default: 3)
+    #[argh(option)]
+    webhook_retries: Option<u32>,
+
+    /// webhook request timeout (seconds, default: 10)
+    #[argh(option)]
+    webhook_timeout: Option<u64>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    History(HistoryOpts),
+    Tui(TuiOpts),
+    Bar(BarOpts),
+}
+
+/// Show the recorded history of progress changes
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "history")]
+struct HistoryOpts {
+    /// only show this realm, e.g. `sc-ladder`; may be repeated
+    #[argh(option)]
+    realm: Vec<Realm>,
+
+    /// only show this region; may be repeated
+    #[argh(option)]
+    region: Vec<Region>,
+
+    /// only show records from this time on, as a UNIX timestamp,
+    /// an RFC 3339 date or a duration like `6h` or `7d` ago
+    #[argh(option)]
+    since: Option<Timestamp>,
+
+    /// only show records up to this time, in the same formats as --since
+    #[argh(option)]
+    until: Option<Timestamp>,
+
+    /// show at most this many of the most recent records
+    #[argh(option)]
+    limit: Option<u32>,
+
+    /// show every observation instead of only the changes
+    #[argh(switch)]
+    observations: bool,
+}
+
+/// Show live progress in an interactive terminal dashboard
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "tui")]
+struct TuiOpts {
+    /// also notify through the configured exec hooks and webhooks
+    #[argh(switch)]
+    notify: bool,
+}
+
+/// Print the current progress for a status bar like waybar, polybar or i3blocks
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "bar")]
+struct BarOpts {
+    /// output format: waybar (default), polybar or i3blocks
+    #[argh(option, default = "bar::Format::Waybar")]
+    format: bar::Format,
+
+    /// keep running and print a line on every query instead of once
+    #[argh(switch)]
+    watch: bool,
+}
+
+/// A point in time given on the command line, as UNIX timestamp
+#[derive(Debug, Clone, Copy)]
+struct Timestamp(u64);
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(secs) = s.parse() {
+            return Ok(Timestamp(secs));
+        }
+
+        if let Ok(time) = time::OffsetDateTime::parse(s, &Rfc3339) {
+            return Ok(Timestamp(time.unix_timestamp().max(0) as u64));
+        }
+
+        let (n, unit) = match s.char_indices().last() {
+            Some((i, 's')) => (&s[..i], 1),
+            Some((i, 'm')) => (&s[..i], 60),
+            Some((i, 'h')) => (&s[..i], 60 * 60),
+            Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+            _ => (s, 0),
+        };
+
+        match n.parse::<u64>() {
+            Ok(n) if unit > 0 => Ok(Timestamp(
+                state::now().saturating_sub(n.saturating_mul(unit)),
+            )),
+            _ => Err(format!(
+                "invalid time `{}`: expected a UNIX timestamp, an RFC 3339 date or e.g. `6h`",
+                s
+            )),
+        }
+    }
+}
+
+impl Opts {
+    /// Load the config file and apply the command line overrides
+    fn config(&self) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => match config::default_path() {
+                Some(path) if path.exists() => Config::load(&path)?,
+                _ => Config::default(),
+            },
+        };
+
+        if let Some(interval) = self.interval {
+            config.interval = interval;
+        }
+
+        if let Some(url) = &self.diablo2io_url {
+            config.sources.diablo2io.url = url.clone();
+        }
+
+        if let Some(token) = &self.d2runewizard_token {
+            config.sources.d2runewizard.token = Some(token.clone());
+        }
+
+        if let Some(path) = &self.state_file {
+            config.state_file = Some(path.clone());
+        }
+
+        if let Some(max_age) = self.max_state_age {
+            config.max_state_age = max_age;
+        }
+
+        if let Some(path) = &self.history_file {
+            config.history_file = Some(path.clone());
+        }
+
+        if self.no_history {
+            config.history = false;
+        }
+
+        if self.no_cache {
+            config.cache = false;
+        }
+
+        if let Some(listen) = self.listen {
+            config.listen = Some(listen);
+        }
+
+        if let Some(realms) = self.realms() {
+            config.realms = realms.into_iter().map(RealmConfig::new).collect();
+        } else if config.realms.is_empty() {
+            config.realms.push(RealmConfig::new(Realm::default()));
+        }
+
+        if !self.source.is_empty() {
+            for realm in &mut config.realms {
+                realm.sources = self.source.clone();
+            }
+        }
+
+        if !self.region.is_empty() {
+            for realm in &mut config.realms {
+                realm.regions = self.region.clone();
+                realm.regions.sort();
+                realm.regions.dedup();
+            }
+        }
+
+        let notifiers = self.notifiers()?;
+        if !notifiers.is_empty() {
+            config.notifiers = notifiers;
+        } else if config.notifiers.is_empty() {
+            config.notifiers.push(NotifierConfig::Desktop);
+        }
+
+        if self.min_level.is_some() || self.rising_only {
+            config.rules = vec![Rule {
+                realms: None,
+                regions: None,
+                min_level: self.min_level.unwrap_or(1),
+                rising_only: self.rising_only,
+            }];
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Realms selected on the command line, if any
+    fn realms(&self) -> Option<Vec<Realm>> {
+        if self.all_realms {
+            Some(Realm::ALL.to_vec())
+        } else if !self.realm.is_empty() {
+            let mut realms = self.realm.clone();
+            realms.sort();
+            realms.dedup();
+            Some(realms)
+        } else if self.ladder || self.hardcore {
+            Some(vec![Realm {
+                ladder: self.ladder,
+                hardcore: self.hardcore,
+            }])
+        } else {
+            None
+        }
+    }
+
+    /// Notifiers configured on the command line
+    fn notifiers(&self) -> Result<Vec<NotifierConfig>> {
+        let mut notifiers = Vec::new();
+
+        for kind in &self.notifier {
+            let notifier = match kind {
+                notifier::Kind::Desktop => NotifierConfig::Desktop,
+                notifier::Kind::Stdout => NotifierConfig::Stdout,
+            };
+
+            if !notifiers.contains(&notifier) {
+                notifiers.push(notifier);
+            }
+        }
+
+        for command in &self.exec {
+            notifiers.push(NotifierConfig::Exec {
+                command: command.clone(),
+            });
+        }
+
+        let retries = self.webhook_retries.unwrap_or_else(config::default_retries);
+        let timeout = self.webhook_timeout.unwrap_or_else(config::default_timeout);
+
+        if !self.webhook.is_empty() {
+            let template = match &self.webhook_template {
+                Some(template) => {
+                    Some(serde_json::from_str(template).context("parsing --webhook-template")?)
+                }
+                None => None,
+            };
+
+            notifiers.push(NotifierConfig::Webhook {
+                urls: self.webhook.clone(),
+                template,
+                retries,
+                timeout,
+            });
+        }
+
+        if !self.discord_webhook.is_empty() {
+            notifiers.push(NotifierConfig::Discord {
+                urls: self.discord_webhook.clone(),
+                mentions: self.discord_mention.clone(),
+                retries,
+                timeout,
+            });
+        }
+
+        if !self.slack_webhook.is_empty() {
+            notifiers.push(NotifierConfig::Slack {
+                urls: self.slack_webhook.clone(),
+                mentions: self.slack_mention.clone(),
+                retries,
+                timeout,
+            });
+        }
+
+        Ok(notifiers)
+    }
+}
+
+#[derive(
+    Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Serialize, Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+struct Realm {
+    ladder: bool,
+    hardcore: bool,
+}
+
+impl Realm {
+    const ALL: [Realm; 4] = [
+        Realm {
+            ladder: false,
+            hardcore: false,
+        },
+        Realm {
+            ladder: true,
+            hardcore: false,
+        },
+        Realm {
+            ladder: false,
+            hardcore: true,
+        },
+        Realm {
+            ladder: true,
+            hardcore: true,
+        },
+    ];
+
+    /// Short identifier of the realm, as accepted by `FromStr`
+    fn id(self) -> &'static str {
+        match (self.hardcore, self.ladder) {
+            (false, false) => "sc-nonladder",
+            (false, true) => "sc-ladder",
+            (true, false) => "hc-nonladder",
+            (true, true) => "hc-ladder",
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct Progress {
-    progress: String,
-    region: String,
+impl TryFrom<String> for Realm {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Realm> for String {
+    fn from(realm: Realm) -> Self {
+        realm.id().to_owned()
+    }
 }
 
-impl From<&Progress> for i32 {
-    fn from(other: &Progress) -> Self {
-        str::parse(&other.progress).unwrap()
+impl FromStr for Realm {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let (core, ladder) = lower
+            .split_once('-')
+            .ok_or_else(|| format!("invalid realm `{}`, expected e.g. `sc-ladder`", s))?;
+
+        let hardcore = match core {
+            "sc" | "softcore" => false,
+            "hc" | "hardcore" => true,
+            _ => return Err(format!("invalid realm `{}`: expected `sc` or `hc`", s)),
+        };
+
+        let ladder = match ladder {
+            "l" | "ladder" => true,
+            "nl" | "nonladder" | "non-ladder" => false,
+            _ => {
+                return Err(format!(
+                    "invalid realm `{}`: expected `ladder` or `nonladder`",
+                    s
+                ))
+            }
+        };
+
+        Ok(Realm { ladder, hardcore })
     }
 }
 
-impl fmt::Display for Progress {
+impl fmt::Display for Realm {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let region = match self.region.as_str() {
-            "1" => "Americas",
-            "2" => "Europe",
-            "3" => "Asia",
-            _ => "Unknown",
+        let core = if self.hardcore {
+            "Hardcore"
+        } else {
+            "Softcore"
         };
+        let ladder = if self.ladder { "Ladder" } else { "Non-Ladder" };
+        write!(f, "{} {}", core, ladder)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+enum Region {
+    Americas,
+    Europe,
+    Asia,
+}
+
+impl Region {
+    const ALL: [Region; 3] = [Region::Americas, Region::Europe, Region::Asia];
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "1" => Some(Region::Americas),
+            "2" => Some(Region::Europe),
+            "3" => Some(Region::Asia),
+            _ => None,
+        }
+    }
+
+    /// The region's code in the diablo2.io API
+    fn code(self) -> &'static str {
+        match self {
+            Region::Americas => "1",
+            Region::Europe => "2",
+            Region::Asia => "3",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Region::Americas => "Americas",
+            Region::Europe => "Europe",
+            Region::Asia => "Asia",
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "americas" | "1" => Ok(Region::Americas),
+            "europe" | "2" => Ok(Region::Europe),
+            "asia" | "3" => Ok(Region::Asia),
+            _ => Err(format!(
+                "invalid region `{}`: expected americas, europe or asia",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = String;
 
-        write!(f, "Progress for {}: {}/6", region, self.progress)
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.name().to_ascii_lowercase()
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
     }
 }
 
-#[derive(Debug, Default, PartialEq, Copy, Clone)]
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
 struct Status {
     americas: i32,
     europe: i32,
@@ -61,99 +624,247 @@ struct Status {
 }
 
 impl Status {
-    fn update_americas(&mut self, new: i32) -> Result<()> {
-        if new != self.americas {
-            notify("Americas", self.americas, new)?;
-            self.americas = new;
+    fn get(&self, region: Region) -> i32 {
+        match region {
+            Region::Americas => self.americas,
+            Region::Europe => self.europe,
+            Region::Asia => self.asia,
         }
-
-        Ok(())
     }
 
-    fn update_europe(&mut self, new: i32) -> Result<()> {
-        if new != self.europe {
-            notify("Europe", self.europe, new)?;
-            self.europe = new;
+    fn get_mut(&mut self, region: Region) -> &mut i32 {
+        match region {
+            Region::Americas => &mut self.americas,
+            Region::Europe => &mut self.europe,
+            Region::Asia => &mut self.asia,
         }
-
-        Ok(())
     }
 
-    fn update_asia(&mut self, new: i32) -> Result<()> {
-        if new != self.asia {
-            notify("Asia", self.asia, new)?;
-            self.asia = new;
+    /// Set the level of `region`, returning the change if there is one
+    fn update(&mut self, realm: Realm, region: Region, new: i32) -> Result<Option<Event>> {
+        let current = self.get_mut(region);
+
+        if new == *current {
+            return Ok(None);
         }
 
-        Ok(())
+        let event = Event::new(realm, region, *current, new)?;
+        *current = new;
+        Ok(Some(event))
     }
 }
 
-fn notify(region: &str, old: i32, new: i32) -> Result<()> {
-    let (title, urgency) = match new {
-        1 => ("DClone is far away", Urgency::Low),
-        2 | 3 | 4 => ("DClone is nearing...", Urgency::Normal),
-        5 => ("DClone is about to walk!", Urgency::Critical),
-        6 => ("DClone is walking!", Urgency::Critical),
-        n => return Err(anyhow!("Unknown progress value: {}", n)),
-    };
+/// Estimate the walk of a region at `level`, based on when it reached the level
+fn estimate_now(
+    history: &History,
+    realm: Realm,
+    region: Region,
+    level: i32,
+) -> Result<Option<eta::Estimate>> {
+    let elapsed = history
+        .level_started(realm, region, level)?
+        .map(|started| state::now().saturating_sub(started));
+    eta::estimate(history, realm, region, level, elapsed)
+}
 
-    let msg = if old == 0 {
-        format!("New status: {}", new)
-    } else {
-        format!("Status changed from {} to {}", old, new)
-    };
+/// A region's progress as currently reported by the API
+struct Reading {
+    realm: Realm,
+    region: Region,
+    level: i32,
+    /// when progress was last reported to the source
+    reported_at: Option<u64>,
+    /// whether there's been no new report for longer than `stale_after`
+    stale: bool,
+    estimate: Option<eta::Estimate>,
+}
 
-    let title = format!("{}: {}", region, title);
+/// The outcome of querying every configured realm once
+struct Readings {
+    readings: Vec<Reading>,
+    /// realms none of whose sources answered, with the last error
+    failed: Vec<(Realm, FetchError)>,
+}
 
-    let notification = libnotify::Notification::new(&title, Some(msg.as_str()), Some("annihilus"));
-    notification.set_urgency(urgency);
-    notification.show()?;
-    Ok(())
+/// Query the current progress of every configured realm and region.
+///
+/// A realm that can't be queried doesn't keep the others from being read.
+async fn fetch_readings(client: &Client, config: &Config, history: Option<&History>) -> Readings {
+    let sources = source::Sources::new(config);
+    let mut readings = Vec::new();
+    let mut failed = Vec::new();
+
+    for RealmConfig {
+        realm,
+        regions,
+        sources: names,
+    } in &config.realms
+    {
+        let realm = *realm;
+        let mut reports = Vec::new();
+        let mut error = None;
+        for source in sources.select(names) {
+            match source.fetch(client, realm).await {
+                Ok(progress) => reports.push((source.name(), progress)),
+                Err(e) => {
+                    log::warn!(
+                        "[{}] Failed to query progress from {}: {}",
+                        realm,
+                        source.name(),
+                        e
+                    );
+                    error = Some(e);
+                }
+            }
+        }
+
+        if let (true, Some(e)) = (reports.is_empty(), error) {
+            failed.push((realm, e));
+            continue;
+        }
+
+        let consensus = source::consensus::reconcile(&reports, config.max_report_age, state::now());
+        for conflict in &consensus.conflicts {
+            if regions.contains(&conflict.region) && conflict.spread() > 1 {
+                log::warn!(
+                    "[{}] Sources disagree on {}: {}",
+                    realm,
+                    conflict.region,
+                    conflict
+                );
+            }
+        }
+
+        for progress in consensus.progress {
+            let region = progress.region;
+            if !regions.contains(&region) {
+                continue;
+            }
+
+            let level = progress.level.get();
+            let estimate = history.and_then(|history| {
+                estimate_now(history, realm, region, level).unwrap_or_else(|e| {
+                    log::warn!("Failed to estimate walk: {:#}", e);
+                    None
+                })
+            });
+
+            readings.push(Reading {
+                realm,
+                region,
+                level,
+                reported_at: progress.reported_at,
+                stale: tracker::stale_age(config, progress.reported_at, state::now()).is_some(),
+                estimate,
+            });
+        }
+    }
+
+    Readings { readings, failed }
 }
 
-fn build_client() -> Result<reqwest::Client> {
-    let client = reqwest::Client::builder()
-        .user_agent("dclone-tracker/0.1.0 https://github.com/tronje/dclone-tracker")
-        .build()?;
-    Ok(client)
+async fn run_once(config: Config, format: Option<output::Format>) -> Result<Readings> {
+    let client = Client::new(&config)?;
+    let history = tracker::read_history(&config);
+    let readings = fetch_readings(&client, &config, history.as_ref()).await;
+
+    if let Some(format) = format {
+        output::write(format, &readings.readings, &mut std::io::stdout().lock())?;
+        return Ok(readings);
+    }
+
+    for reading in &readings.readings {
+        let progress = format!(
+            "Progress for {}: {}/6{}",
+            reading.region.name(),
+            reading.level,
+            if reading.stale { " (stale)" } else { "" }
+        );
+        match &reading.estimate {
+            Some(estimate) => log::info!("[{}] {}, {}", reading.realm, progress, estimate),
+            None => log::info!("[{}] {}", reading.realm, progress),
+        }
+    }
+    Ok(readings)
 }
 
-fn build_url(ladder: bool, hardcore: bool) -> String {
-    let ladder = if ladder { 1 } else { 2 };
-    let hardcore = if hardcore { 1 } else { 2 };
-    format!(
-        "https://diablo2.io/dclone_api.php?ladder={}&hc={}",
-        ladder, hardcore
-    )
+fn new_timer(interval: u64) -> tokio::time::Interval {
+    let period = Duration::from_secs(interval);
+    tokio::time::interval_at(tokio::time::Instant::now() + period, period)
 }
 
-async fn run_once(opts: Opts) -> Result<()> {
-    let url = build_url(opts.ladder, opts.hardcore);
-    let client = build_client()?;
+fn show_history(config: &Config, opts: &HistoryOpts) -> Result<()> {
+    let path = match &config.history_file {
+        Some(path) => path.clone(),
+        None => history::default_path()?,
+    };
 
-    let response = client
-        .get(&url)
-        .send()
-        .await?
-        .json::<Vec<Progress>>()
-        .await?;
-    for progress in response {
-        log::info!("{}", progress);
+    if !path.exists() {
+        bail!("no history recorded at {}", path.display());
     }
+
+    let history = History::open_read_only(&path)?;
+    let filter = history::Filter {
+        realms: opts.realm.clone(),
+        regions: opts.region.clone(),
+        since: opts.since.map(|t| t.0),
+        until: opts.until.map(|t| t.0),
+        limit: opts.limit,
+    };
+
+    for record in history.query(&filter, opts.observations)? {
+        let level = match record.old {
+            Some(old) => format!("{} -> {}", old, record.level),
+            None => record.level.to_string(),
+        };
+        let reported = match record.reported_at {
+            Some(time) => history::format_time(time),
+            None => "-".to_owned(),
+        };
+
+        println!(
+            "{}  {:<12}  {:<8}  {:<11}  {:<6}  reported {}",
+            history::format_time(record.fetched_at),
+            record.realm,
+            record.region,
+            record.kind,
+            level,
+            reported
+        );
+    }
+
     Ok(())
 }
 
-async fn run(opts: Opts) -> Result<()> {
-    let url = build_url(opts.ladder, opts.hardcore);
+fn start_server(
+    listen: Option<SocketAddr>,
+    snapshots: &watch::Sender<tracker::Snapshot>,
+    metrics: &metrics::Metrics,
+    events: &broadcast::Sender<Event>,
+) -> Result<Option<tokio::task::JoinHandle<()>>> {
+    match listen {
+        Some(addr) => Ok(Some(server::spawn(
+            addr,
+            snapshots.subscribe(),
+            metrics.clone(),
+            events.clone(),
+        )?)),
+        None => Ok(None),
+    }
+}
+
+async fn run(opts: &Opts, config: Config) -> Result<()> {
+    let metrics = metrics::Metrics::new()?;
+    let mut client = Client::new(&config)?.with_metrics(metrics.clone());
+    let (events, _) = broadcast::channel(64);
+    let mut tracker = Tracker::new(config, &client, metrics.clone(), events.clone(), true)?;
 
-    let mut timer = tokio::time::interval(Duration::from_secs(opts.interval));
-    let client = build_client()?;
+    let (snapshots, _) = watch::channel(tracker.snapshot());
+    let mut server = start_server(tracker.config().listen, &snapshots, &metrics, &events)?;
 
     let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt())?;
     let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate())?;
-
-    let mut status = Status::default();
+    let mut sighup = tokio::signal::unix::signal(SignalKind::hangup())?;
 
     loop {
         select! {
@@ -167,29 +878,38 @@ async fn run(opts: Opts) -> Result<()> {
                 break;
             }
 
-            _ = timer.tick() => {
-                let response = match client.get(&url).send().await?.json::<Vec<Progress>>().await {
-                    Ok(values) => values,
-                    Err(e) => {
-                        log::error!("{}", e);
-                        continue;
-                    }
-                };
+            _ = sighup.recv() => {
+                let listen = tracker.config().listen;
+                let result = opts.config().and_then(|config| tracker.reload(config, &mut client));
 
-                log::debug!("Received response: {:#?}", response);
+                if let Err(e) = result {
+                    log::error!("Failed to reload configuration, keeping the old one: {:#}", e);
+                    continue;
+                }
 
-                for progress in response {
-                    match progress.region.as_str() {
-                        "1" => status.update_americas(str::parse(&progress.progress)?)?,
-                        "2" => status.update_europe(str::parse(&progress.progress)?)?,
-                        "3" => status.update_asia(str::parse(&progress.progress)?)?,
-                        n => log::warn!("Unexpected region code: {}", n),
+                if tracker.config().listen != listen {
+                    if let Some(server) = server.take() {
+                        server.abort();
                     }
+
+                    server = start_server(tracker.config().listen, &snapshots, &metrics, &events).unwrap_or_else(|e| {
+                        log::error!("Failed to start status API: {:#}", e);
+                        None
+                    });
                 }
+
+                snapshots.send_replace(tracker.snapshot());
+            }
+
+            _ = tokio::time::sleep_until(tracker.next_poll()) => {
+                tracker.poll(&client).await?;
+                snapshots.send_replace(tracker.snapshot());
             }
         }
     }
 
+    tracker.save();
+
     Ok(())
 }
 
@@ -197,20 +917,68 @@ async fn run(opts: Opts) -> Result<()> {
 async fn main() -> Result<()> {
     let opts = argh::from_env::<Opts>();
 
-    SimpleLogger::new()
-        .with_level(log::LevelFilter::Debug)
-        .init()?;
+    // the dashboard owns the terminal, so log messages go into its event log instead
+    if let Some(Command::Tui(tui_opts)) = &opts.command {
+        let log = tui::init_logger()?;
+        return tui::run(opts.config()?, log, tui_opts.notify).await;
+    }
 
-    log::info!("Data courtesy of diablo2.io");
+    // status bars tend to collect stderr into their own logs, so keep it quiet there
+    let level = match &opts.command {
+        Some(Command::Bar(_)) => log::LevelFilter::Warn,
+        _ => log::LevelFilter::Debug,
+    };
+    SimpleLogger::new().with_level(level).init()?;
 
-    if opts.oneshot {
-        run_once(opts).await?;
-        return Ok(());
+    let config = opts.config()?;
+
+    if let Some(Command::Bar(bar_opts)) = &opts.command {
+        return bar::run(config, bar_opts.format, bar_opts.watch).await;
+    }
+
+    if let Some(Command::History(history_opts)) = &opts.command {
+        return show_history(&config, history_opts);
     }
 
-    libnotify::init("dclone-tracker").map_err(|e| anyhow!("{}", e))?;
-    let result = run(opts).await;
-    libnotify::uninit();
+    if !opts.oneshot && (opts.format.is_some() || opts.threshold.is_some()) {
+        bail!("--format and --threshold only apply to --oneshot");
+    }
+
+    if opts
+        .threshold
+        .is_some_and(|threshold| !(1..=6).contains(&threshold))
+    {
+        bail!("--threshold must be between 1 and 6");
+    }
+
+    log::info!("Data courtesy of {}", source::credits(&config));
+
+    if opts.oneshot {
+        let realms = config.realms.len();
+        let result = run_once(config, opts.format).await;
+        let threshold = match opts.threshold {
+            Some(threshold) => threshold,
+            None => {
+                let failed = result?.failed.len();
+                if failed > 0 {
+                    bail!("{} of {} realms could not be queried", failed, realms);
+                }
+                return Ok(());
+            }
+        };
+
+        // a realm that couldn't be queried may well be at the threshold
+        let code = match result {
+            Ok(readings) if readings.readings.iter().any(|r| r.level >= threshold) => 0,
+            Ok(readings) if readings.failed.is_empty() => 1,
+            Ok(_) => 2,
+            Err(e) => {
+                log::error!("{:#}", e);
+                2
+            }
+        };
+        std::process::exit(code);
+    }
 
-    result
+    run(&opts, config).await
 }
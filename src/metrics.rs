@@ -0,0 +1,122 @@
+use crate::client::FetchError;
+use crate::{Realm, Region};
+use anyhow::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// Prometheus metrics about the tracked progress and the API queries
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    progress: IntGaugeVec,
+    successes: IntCounterVec,
+    failures: IntCounterVec,
+    latency: HistogramVec,
+    last_success: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let progress = IntGaugeVec::new(
+            Opts::new("dclone_progress", "Current DClone progress (1-6)"),
+            &["realm", "region"],
+        )?;
+        let successes = IntCounterVec::new(
+            Opts::new("dclone_fetch_successes_total", "Successful API queries"),
+            &["realm"],
+        )?;
+        let failures = IntCounterVec::new(
+            Opts::new("dclone_fetch_failures_total", "Failed API queries"),
+            &["realm", "kind"],
+        )?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new("dclone_api_latency_seconds", "Duration of API requests"),
+            &["source"],
+        )?;
+        let last_success = IntGaugeVec::new(
+            Opts::new(
+                "dclone_last_success_timestamp_seconds",
+                "UNIX timestamp of the last successful API query",
+            ),
+            &["realm"],
+        )?;
+
+        let registry = Registry::new();
+        registry.register(Box::new(progress.clone()))?;
+        registry.register(Box::new(successes.clone()))?;
+        registry.register(Box::new(failures.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        registry.register(Box::new(last_success.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            progress,
+            successes,
+            failures,
+            latency,
+            last_success,
+        })
+    }
+
+    pub fn set_progress(&self, realm: Realm, region: Region, level: i32) {
+        self.progress
+            .with_label_values(&[realm.id(), &String::from(region)])
+            .set(i64::from(level));
+    }
+
+    pub fn fetch_succeeded(&self, realm: Realm, timestamp: u64) {
+        self.successes.with_label_values(&[realm.id()]).inc();
+        self.last_success
+            .with_label_values(&[realm.id()])
+            .set(timestamp as i64);
+    }
+
+    pub fn fetch_failed(&self, realm: Realm, error: &FetchError) {
+        self.failures
+            .with_label_values(&[realm.id(), error_kind(error)])
+            .inc();
+    }
+
+    /// Record how long a request to `source` took until its response arrived
+    pub fn request_sent(&self, source: &str, latency: Duration) {
+        self.latency
+            .with_label_values(&[source])
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Render all metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            log::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+fn error_kind(error: &FetchError) -> &'static str {
+    let error = match error {
+        FetchError::Http(error) => error,
+        FetchError::RateLimited(_) => return "rate_limited",
+        FetchError::Decode(_) => return "decode",
+        FetchError::Schema(_) => return "schema",
+    };
+
+    if error.is_timeout() {
+        "timeout"
+    } else if error.is_connect() {
+        "connect"
+    } else if error.is_status() {
+        "status"
+    } else if error.is_decode() {
+        "decode"
+    } else if error.is_body() {
+        "body"
+    } else if error.is_request() {
+        "request"
+    } else {
+        "other"
+    }
+}
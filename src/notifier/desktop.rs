@@ -0,0 +1,58 @@
+use super::{Event, Notifier, Urgency};
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of live `Desktop` instances.
+///
+/// libnotify is initialized process-wide, so it may only be uninitialized once
+/// the last instance is gone, e.g. when notifiers are rebuilt on reload.
+static INSTANCES: AtomicUsize = AtomicUsize::new(0);
+
+/// Desktop notifications via libnotify
+pub struct Desktop {
+    _private: (),
+}
+
+impl Desktop {
+    pub fn new() -> Result<Self> {
+        if INSTANCES.fetch_add(1, Ordering::SeqCst) == 0 {
+            if let Err(e) = libnotify::init("dclone-tracker") {
+                INSTANCES.fetch_sub(1, Ordering::SeqCst);
+                return Err(anyhow!("{}", e));
+            }
+        }
+
+        Ok(Desktop { _private: () })
+    }
+}
+
+impl Drop for Desktop {
+    fn drop(&mut self) {
+        if INSTANCES.fetch_sub(1, Ordering::SeqCst) == 1 {
+            libnotify::uninit();
+        }
+    }
+}
+
+impl Notifier for Desktop {
+    fn name(&self) -> &str {
+        "desktop"
+    }
+
+    fn notify(&self, event: &Event) -> Result<()> {
+        let urgency = match event.urgency {
+            Urgency::Low => libnotify::Urgency::Low,
+            Urgency::Normal => libnotify::Urgency::Normal,
+            Urgency::Critical => libnotify::Urgency::Critical,
+        };
+
+        let title = event.title();
+        let msg = event.message();
+
+        let notification =
+            libnotify::Notification::new(&title, Some(msg.as_str()), Some("annihilus"));
+        notification.set_urgency(urgency);
+        notification.show()?;
+        Ok(())
+    }
+}
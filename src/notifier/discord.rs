@@ -0,0 +1,62 @@
+use super::{Event, Mention};
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Build a Discord webhook payload with a single embed, crediting the sites in `credits`
+pub fn payload(event: &Event, mentions: &[Mention], credits: &str) -> Value {
+    let timestamp = OffsetDateTime::from(event.time).format(&Rfc3339).ok();
+
+    let mut fields =
+        vec![json!({ "name": "Realm", "value": event.realm.to_string(), "inline": true })];
+    if let Some(region) = event.region {
+        fields.push(json!({ "name": "Region", "value": region.to_string(), "inline": true }));
+    }
+
+    if event.region.is_some() && !event.is_health() {
+        fields.push(
+            json!({ "name": "Progress", "value": format!("{}/6", event.new), "inline": true }),
+        );
+    }
+
+    let mut payload = json!({
+        "embeds": [{
+            "title": event.title(),
+            "description": event.message(),
+            "color": event.urgency.color(),
+            "fields": fields,
+            "timestamp": timestamp,
+            "footer": { "text": format!("Data courtesy of {}", credits) },
+        }],
+    });
+
+    if event.is_imminent() && !mentions.is_empty() {
+        let content: Vec<String> = mentions.iter().map(mention).collect();
+        let roles: Vec<&str> = mentions
+            .iter()
+            .filter_map(|m| match m {
+                Mention::Role(id) => Some(id.as_str()),
+                Mention::User(_) => None,
+            })
+            .collect();
+        let users: Vec<&str> = mentions
+            .iter()
+            .filter_map(|m| match m {
+                Mention::User(id) => Some(id.as_str()),
+                Mention::Role(_) => None,
+            })
+            .collect();
+
+        payload["content"] = json!(content.join(" "));
+        payload["allowed_mentions"] = json!({ "roles": roles, "users": users });
+    }
+
+    payload
+}
+
+fn mention(mention: &Mention) -> String {
+    match mention {
+        Mention::Role(id) => format!("<@&{}>", id),
+        Mention::User(id) => format!("<@{}>", id),
+    }
+}
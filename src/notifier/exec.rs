@@ -0,0 +1,60 @@
+use super::{Event, Notifier};
+use anyhow::Result;
+use std::time::UNIX_EPOCH;
+use tokio::process::Command;
+
+/// Run a shell command for every event.
+///
+/// The event is passed to the command through `DCLONE_*` environment variables.
+pub struct Exec {
+    command: String,
+}
+
+impl Exec {
+    pub fn new(command: String) -> Self {
+        Exec { command }
+    }
+}
+
+impl Notifier for Exec {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn notify(&self, event: &Event) -> Result<()> {
+        let timestamp = event
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("DCLONE_REALM", event.realm.to_string())
+            .env("DCLONE_REGION", event.region_name())
+            .env("DCLONE_EVENT", event.kind.to_string())
+            .env("DCLONE_OLD", event.old.to_string())
+            .env("DCLONE_NEW", event.new.to_string())
+            .env("DCLONE_URGENCY", event.urgency.to_string())
+            .env("DCLONE_STALE", event.stale_for.is_some().to_string())
+            .env("DCLONE_TITLE", event.title())
+            .env("DCLONE_MESSAGE", event.message())
+            .env("DCLONE_TIMESTAMP", timestamp.to_string())
+            .spawn()?;
+
+        // don't hold up the tracker while the hook runs, but still reap it
+        let command = self.command.clone();
+        tokio::spawn(async move {
+            match child.wait().await {
+                Ok(status) if !status.success() => {
+                    log::warn!("`{}` exited with {}", command, status);
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Failed to wait for `{}`: {}", command, e),
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,450 @@
+use crate::config::NotifierConfig;
+use crate::eta::Estimate;
+use crate::{Realm, Region};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod desktop;
+mod discord;
+mod exec;
+mod slack;
+mod stdout;
+mod webhook;
+
+pub use desktop::Desktop;
+pub use exec::Exec;
+pub use stdout::Stdout;
+pub use webhook::{Format, Template, Webhook};
+
+/// How pressing a status change is
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// How pressing it is for a region to be at `level`
+    pub fn for_level(level: i32) -> Option<Self> {
+        match level {
+            1 => Some(Urgency::Low),
+            2..=4 => Some(Urgency::Normal),
+            5 | 6 => Some(Urgency::Critical),
+            _ => None,
+        }
+    }
+
+    /// RGB colour used for the urgency in chat messages
+    pub fn color(self) -> u32 {
+        match self {
+            Urgency::Low => 0x2ecc71,
+            Urgency::Normal => 0xf1c40f,
+            Urgency::Critical => 0xe74c3c,
+        }
+    }
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        };
+        f.write_str(s)
+    }
+}
+
+/// What kind of change an event is
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EventKind {
+    /// Progress went up or down
+    Change,
+    /// Progress was reset after DClone walked
+    Walk,
+    /// Queries of a realm keep failing
+    Degraded,
+    /// Queries of a realm succeed again after it was degraded
+    Recovered,
+    /// The sources of a realm disagree about a region
+    Conflict,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            EventKind::Change => "change",
+            EventKind::Walk => "walk",
+            EventKind::Degraded => "degraded",
+            EventKind::Recovered => "recovered",
+            EventKind::Conflict => "conflict",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A change in DClone progress for one region of a realm, or in how well a realm
+/// can be tracked
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub realm: Realm,
+    /// the region the event is about, none for degraded and recovered events
+    pub region: Option<Region>,
+    pub kind: EventKind,
+    pub old: i32,
+    pub new: i32,
+    pub time: SystemTime,
+    pub urgency: Urgency,
+    /// when DClone is expected to walk, if there's enough history to tell
+    pub estimate: Option<Estimate>,
+    /// age (seconds) of the report the event is based on, if it's stale
+    pub stale_for: Option<u64>,
+    summary: &'static str,
+    /// message of events that aren't about progress
+    detail: Option<String>,
+}
+
+impl Event {
+    pub fn new(realm: Realm, region: Region, old: i32, new: i32) -> Result<Self> {
+        let (kind, summary, urgency) = match new {
+            _ if Event::is_walk(old, new) => {
+                (EventKind::Walk, "DClone has walked!", Urgency::Critical)
+            }
+            1 => (EventKind::Change, "DClone is far away", Urgency::Low),
+            2..=4 => (EventKind::Change, "DClone is nearing...", Urgency::Normal),
+            5 => (
+                EventKind::Change,
+                "DClone is about to walk!",
+                Urgency::Critical,
+            ),
+            6 => (EventKind::Change, "DClone is walking!", Urgency::Critical),
+            n => return Err(anyhow!("Unknown progress value: {}", n)),
+        };
+
+        Ok(Event {
+            realm,
+            region: Some(region),
+            kind,
+            old,
+            new,
+            time: SystemTime::now(),
+            urgency,
+            estimate: None,
+            stale_for: None,
+            summary,
+            detail: None,
+        })
+    }
+
+    /// Queries of `realm` failed `failures` times in a row
+    pub fn degraded(realm: Realm, failures: u32, error: &str) -> Self {
+        Event {
+            realm,
+            region: None,
+            kind: EventKind::Degraded,
+            old: 0,
+            new: 0,
+            time: SystemTime::now(),
+            urgency: Urgency::Normal,
+            estimate: None,
+            stale_for: None,
+            summary: "Tracking is degraded",
+            detail: Some(format!(
+                "{} queries in a row failed, the last one with: {}",
+                failures, error
+            )),
+        }
+    }
+
+    /// Queries of `realm` succeed again after failing `failures` times in a row
+    pub fn recovered(realm: Realm, failures: u32) -> Self {
+        Event {
+            realm,
+            region: None,
+            kind: EventKind::Recovered,
+            old: 0,
+            new: 0,
+            time: SystemTime::now(),
+            urgency: Urgency::Low,
+            estimate: None,
+            stale_for: None,
+            summary: "Tracking has recovered",
+            detail: Some(format!("Queries succeed again after {} failures", failures)),
+        }
+    }
+
+    /// The sources of `realm` disagree about `region`, as described by `detail`
+    pub fn conflict(realm: Realm, region: Region, detail: String) -> Self {
+        Event {
+            realm,
+            region: Some(region),
+            kind: EventKind::Conflict,
+            old: 0,
+            new: 0,
+            time: SystemTime::now(),
+            urgency: Urgency::Normal,
+            estimate: None,
+            stale_for: None,
+            summary: "Sources disagree",
+            detail: Some(detail),
+        }
+    }
+
+    /// Lower the urgency of an event based on a report that is `age` seconds old
+    pub fn soften(&mut self, age: u64) {
+        self.urgency = Urgency::Low;
+        self.stale_for = Some(age);
+    }
+
+    /// Whether the event is about the tracker itself rather than DClone's progress
+    pub fn is_health(&self) -> bool {
+        matches!(
+            self.kind,
+            EventKind::Degraded | EventKind::Recovered | EventKind::Conflict
+        )
+    }
+
+    /// Whether going from `old` to `new` means DClone has walked
+    pub fn is_walk(old: i32, new: i32) -> bool {
+        old >= 5 && new == 1
+    }
+
+    /// The event's fields, as used in webhook templates
+    pub fn fields(&self) -> Map<String, Value> {
+        let timestamp = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut fields = Map::new();
+        fields.insert("realm".into(), json!(self.realm.to_string()));
+        fields.insert("region".into(), json!(self.region_name()));
+        fields.insert("kind".into(), json!(self.kind.to_string()));
+        fields.insert("old".into(), json!(self.old));
+        fields.insert("new".into(), json!(self.new));
+        fields.insert("timestamp".into(), json!(timestamp));
+        fields.insert("urgency".into(), json!(self.urgency.to_string()));
+        fields.insert("stale".into(), json!(self.stale_for.is_some()));
+        fields.insert("title".into(), json!(self.title()));
+        fields.insert("message".into(), json!(self.message()));
+        fields
+    }
+
+    /// The event as JSON, for clients of the status API
+    pub fn to_json(&self) -> Value {
+        let mut fields = self.fields();
+        fields.insert("realm_id".into(), json!(self.realm.id()));
+        fields.insert("estimate".into(), json!(self.estimate));
+        Value::Object(fields)
+    }
+
+    /// Whether DClone is about to walk or walking, going by a report that isn't stale
+    pub fn is_imminent(&self) -> bool {
+        self.new >= 5 && self.stale_for.is_none()
+    }
+
+    /// Name of the region, or an empty string if the event isn't about one
+    pub fn region_name(&self) -> &'static str {
+        self.region.map(Region::name).unwrap_or_default()
+    }
+
+    /// Short, human readable description of the event
+    pub fn title(&self) -> String {
+        match self.region {
+            Some(region) => format!("{} ({}): {}", region, self.realm, self.summary),
+            None => format!("{}: {}", self.realm, self.summary),
+        }
+    }
+
+    /// Longer, human readable description of the event
+    pub fn message(&self) -> String {
+        if let Some(detail) = &self.detail {
+            return detail.clone();
+        }
+
+        let message = if self.kind == EventKind::Walk {
+            format!("Progress was reset from {} to {}", self.old, self.new)
+        } else if self.old == 0 {
+            format!("New status: {}", self.new)
+        } else {
+            format!("Status changed from {} to {}", self.old, self.new)
+        };
+
+        let message = match &self.estimate {
+            Some(estimate) => format!(
+                "{}\n{} {}/6, {}",
+                message,
+                self.region_name(),
+                self.new,
+                estimate
+            ),
+            None => message,
+        };
+
+        match self.stale_for {
+            Some(age) => format!(
+                "{}\nBased on a report from {} minutes ago",
+                message,
+                age / 60
+            ),
+            None => message,
+        }
+    }
+}
+
+/// A role or user to mention in chat messages when DClone is about to walk
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Mention {
+    Role(String),
+    User(String),
+}
+
+impl FromStr for Mention {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("role", id)) if !id.is_empty() => Ok(Mention::Role(id.to_owned())),
+            Some(("user", id)) if !id.is_empty() => Ok(Mention::User(id.to_owned())),
+            _ => Err(format!(
+                "invalid mention `{}`: expected `role:<id>` or `user:<id>`",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Mention {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A backend that status changes are sent to
+pub trait Notifier {
+    /// Name of the backend, used in log messages
+    fn name(&self) -> &str;
+
+    fn notify(&self, event: &Event) -> Result<()>;
+}
+
+/// Built-in backends that don't need any configuration
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Kind {
+    Desktop,
+    Stdout,
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "desktop" => Ok(Kind::Desktop),
+            "stdout" => Ok(Kind::Stdout),
+            _ => Err(format!(
+                "invalid notifier `{}`: expected desktop or stdout",
+                s
+            )),
+        }
+    }
+}
+
+/// All configured notification backends
+#[derive(Default)]
+pub struct Notifiers {
+    backends: Vec<Box<dyn Notifier>>,
+}
+
+impl Notifiers {
+    fn push(&mut self, notifier: impl Notifier + 'static) {
+        self.backends.push(Box::new(notifier));
+    }
+
+    /// Send `event` to every backend.
+    ///
+    /// A failing backend is logged, but doesn't keep the others from being notified.
+    pub fn notify(&self, event: &Event) {
+        for backend in &self.backends {
+            if let Err(e) = backend.notify(event) {
+                log::error!("{} notification failed: {:#}", backend.name(), e);
+            }
+        }
+    }
+}
+
+/// Set up the configured backends, crediting the sites in `credits` where there's room
+pub fn build(
+    configs: &[NotifierConfig],
+    client: &reqwest::Client,
+    credits: &str,
+) -> Result<Notifiers> {
+    let mut notifiers = Notifiers::default();
+
+    for config in configs {
+        match config {
+            NotifierConfig::Desktop => notifiers.push(Desktop::new()?),
+            NotifierConfig::Stdout => notifiers.push(Stdout),
+            NotifierConfig::Exec { command } => notifiers.push(Exec::new(command.clone())),
+            NotifierConfig::Webhook {
+                urls,
+                template,
+                retries,
+                timeout,
+            } => {
+                let template = match template {
+                    Some(template) => Template::new(template.clone())?,
+                    None => Template::default(),
+                };
+                notifiers.push(Webhook::new(
+                    client.clone(),
+                    urls.clone(),
+                    Format::Template(template),
+                    *retries,
+                    Duration::from_secs(*timeout),
+                ));
+            }
+            NotifierConfig::Discord {
+                urls,
+                mentions,
+                retries,
+                timeout,
+            } => notifiers.push(Webhook::new(
+                client.clone(),
+                urls.clone(),
+                Format::Discord {
+                    mentions: mentions.clone(),
+                    credits: credits.to_owned(),
+                },
+                *retries,
+                Duration::from_secs(*timeout),
+            )),
+            NotifierConfig::Slack {
+                urls,
+                mentions,
+                retries,
+                timeout,
+            } => notifiers.push(Webhook::new(
+                client.clone(),
+                urls.clone(),
+                Format::Slack {
+                    mentions: mentions.clone(),
+                    credits: credits.to_owned(),
+                },
+                *retries,
+                Duration::from_secs(*timeout),
+            )),
+        }
+    }
+
+    Ok(notifiers)
+}
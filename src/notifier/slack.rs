@@ -0,0 +1,52 @@
+use super::{Event, Mention};
+use serde_json::{json, Value};
+
+/// Build a Slack webhook payload using Block Kit, crediting the sites in `credits`.
+///
+/// The blocks are wrapped in an attachment, since that's the only way to get a
+/// coloured bar next to the message.
+pub fn payload(event: &Event, mentions: &[Mention], credits: &str) -> Value {
+    let mut text = event.message();
+    if event.is_imminent() && !mentions.is_empty() {
+        let mentions: Vec<String> = mentions.iter().map(mention).collect();
+        text = format!("{} {}", mentions.join(" "), text);
+    }
+
+    let mut fields = vec![json!({ "type": "mrkdwn", "text": format!("*Realm*\n{}", event.realm) })];
+    if let Some(region) = event.region {
+        fields.push(json!({ "type": "mrkdwn", "text": format!("*Region*\n{}", region) }));
+    }
+
+    if event.region.is_some() && !event.is_health() {
+        fields.push(json!({ "type": "mrkdwn", "text": format!("*Progress*\n{}/6", event.new) }));
+    }
+
+    json!({
+        "text": event.title(),
+        "attachments": [{
+            "color": format!("#{:06x}", event.urgency.color()),
+            "blocks": [
+                {
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": event.title() },
+                },
+                {
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": text },
+                    "fields": fields,
+                },
+                {
+                    "type": "context",
+                    "elements": [{ "type": "mrkdwn", "text": format!("Data courtesy of {}", credits) }],
+                },
+            ],
+        }],
+    })
+}
+
+fn mention(mention: &Mention) -> String {
+    match mention {
+        Mention::Role(id) => format!("<!subteam^{}>", id),
+        Mention::User(id) => format!("<@{}>", id),
+    }
+}
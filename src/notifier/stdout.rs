@@ -0,0 +1,16 @@
+use super::{Event, Notifier};
+use anyhow::Result;
+
+/// Print notifications to stdout, one line per event
+pub struct Stdout;
+
+impl Notifier for Stdout {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    fn notify(&self, event: &Event) -> Result<()> {
+        println!("{}: {}", event.title(), event.message());
+        Ok(())
+    }
+}
@@ -0,0 +1,461 @@
+use super::{discord, slack, Event, Mention, Notifier};
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+const FIELDS: [&str; 10] = [
+    "realm",
+    "region",
+    "kind",
+    "old",
+    "new",
+    "timestamp",
+    "urgency",
+    "stale",
+    "title",
+    "message",
+];
+
+/// A JSON document with `{{field}}` placeholders.
+///
+/// A string consisting of nothing but a placeholder is replaced with the field's
+/// value, keeping its JSON type; placeholders embedded in longer strings are
+/// substituted textually.
+#[derive(Debug, Clone)]
+pub struct Template {
+    body: Value,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        let mut body = Map::new();
+        for field in FIELDS {
+            body.insert(
+                field.to_owned(),
+                Value::String(format!("{{{{{}}}}}", field)),
+            );
+        }
+
+        Template {
+            body: Value::Object(body),
+        }
+    }
+}
+
+impl Template {
+    pub fn new(body: Value) -> Result<Self> {
+        check_placeholders(&body)?;
+        Ok(Template { body })
+    }
+
+    pub fn render(&self, event: &Event) -> Value {
+        render(&self.body, &event.fields())
+    }
+}
+
+fn render(value: &Value, fields: &Map<String, Value>) -> Value {
+    match value {
+        Value::String(s) => render_str(s, fields),
+        Value::Array(values) => Value::Array(values.iter().map(|v| render(v, fields)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), render(v, fields)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn render_str(s: &str, fields: &Map<String, Value>) -> Value {
+    if let Some(name) = s.strip_prefix("{{").and_then(|s| s.strip_suffix("}}")) {
+        if let Some(value) = fields.get(name.trim()) {
+            return value.clone();
+        }
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                match fields.get(after[..end].trim()) {
+                    Some(Value::String(value)) => out.push_str(value),
+                    Some(value) => out.push_str(&value.to_string()),
+                    None => out.push_str(&rest[start..start + end + 4]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+
+    Value::String(out)
+}
+
+fn check_placeholders(value: &Value) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("{{") {
+                let after = &rest[start + 2..];
+                let end = after
+                    .find("}}")
+                    .ok_or_else(|| anyhow!("unterminated placeholder in `{}`", s))?;
+                let name = after[..end].trim();
+                if !FIELDS.contains(&name) {
+                    return Err(anyhow!(
+                        "unknown placeholder `{{{{{}}}}}`, expected one of: {}",
+                        name,
+                        FIELDS.join(", ")
+                    ));
+                }
+                rest = &after[end + 2..];
+            }
+            Ok(())
+        }
+        Value::Array(values) => values.iter().try_for_each(check_placeholders),
+        Value::Object(map) => map.values().try_for_each(check_placeholders),
+        _ => Ok(()),
+    }
+}
+
+/// Shape of the payload sent to a webhook
+#[derive(Debug, Clone)]
+pub enum Format {
+    /// Generic JSON, built from a template
+    Template(Template),
+    /// Discord embed, crediting the sites the data comes from
+    Discord {
+        mentions: Vec<Mention>,
+        credits: String,
+    },
+    /// Slack Block Kit message, crediting the sites the data comes from
+    Slack {
+        mentions: Vec<Mention>,
+        credits: String,
+    },
+}
+
+impl Format {
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Template(_) => "webhook",
+            Format::Discord { .. } => "discord",
+            Format::Slack { .. } => "slack",
+        }
+    }
+
+    fn render(&self, event: &Event) -> Value {
+        match self {
+            Format::Template(template) => template.render(event),
+            Format::Discord { mentions, credits } => discord::payload(event, mentions, credits),
+            Format::Slack { mentions, credits } => slack::payload(event, mentions, credits),
+        }
+    }
+}
+
+/// POST a JSON payload to one or more URLs
+pub struct Webhook {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    format: Format,
+    retries: u32,
+    timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(
+        client: reqwest::Client,
+        urls: Vec<String>,
+        format: Format,
+        retries: u32,
+        timeout: Duration,
+    ) -> Self {
+        Webhook {
+            client,
+            urls,
+            format,
+            retries,
+            timeout,
+        }
+    }
+}
+
+impl Webhook {
+    fn request(&self, url: &str, body: &Value) -> reqwest::RequestBuilder {
+        self.client.post(url).timeout(self.timeout).json(body)
+    }
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        self.format.name()
+    }
+
+    fn notify(&self, event: &Event) -> Result<()> {
+        let body = self.format.render(event);
+
+        // deliver in the background, so a slow endpoint doesn't hold up the tracker
+        for (i, url) in self.urls.iter().enumerate() {
+            let request = self.request(url, &body);
+            let endpoint = endpoint(self.format.name(), i, url);
+            let retries = self.retries;
+
+            tokio::spawn(async move {
+                if let Err(e) = post(request, retries, &endpoint).await {
+                    log::error!("{} failed: {:#}", endpoint, e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// How to refer to the `i`th URL of a webhook in log messages.
+///
+/// Webhook URLs tend to carry a secret, so only the host is shown.
+fn endpoint(name: &str, i: usize, url: &str) -> String {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default();
+    format!("{} #{} ({})", name, i + 1, host)
+}
+
+async fn post(request: reqwest::RequestBuilder, retries: u32, endpoint: &str) -> Result<()> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+
+    loop {
+        let request = request
+            .try_clone()
+            .ok_or_else(|| anyhow!("request can't be retried"))?;
+
+        let result = match request.send().await {
+            Ok(response) => response.error_for_status().map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        match result.map_err(reqwest::Error::without_url) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= retries => return Err(e.into()),
+            Err(e) => {
+                attempt += 1;
+                log::warn!(
+                    "{} failed: {}, retrying in {}s",
+                    endpoint,
+                    e,
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Realm, Region};
+    use axum::http::StatusCode;
+    use axum::{routing, Json, Router};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    fn event() -> Event {
+        Event::new(Realm::default(), Region::Europe, 3, 4).unwrap()
+    }
+
+    /// Serve a webhook endpoint on a free port that answers every request with
+    /// `status` after `delay`, passing on the bodies it receives
+    fn stand_in(status: StatusCode, delay: Duration) -> (String, mpsc::UnboundedReceiver<Value>) {
+        let (bodies, received) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            routing::post(move |Json(body): Json<Value>| async move {
+                let _ = bodies.send(body);
+                tokio::time::sleep(delay).await;
+                status
+            }),
+        );
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+
+        (url, received)
+    }
+
+    fn webhook(url: &str, format: Format, retries: u32, timeout: Duration) -> Webhook {
+        Webhook::new(
+            reqwest::Client::new(),
+            vec![url.to_owned()],
+            format,
+            retries,
+            timeout,
+        )
+    }
+
+    #[test]
+    fn whole_placeholders_keep_their_type() {
+        let template = Template::new(json!({
+            "level": "{{new}}",
+            "urgency": "{{ urgency }}",
+            "nested": ["{{old}}", { "region": "{{region}}" }],
+            "fixed": 42,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            template.render(&event()),
+            json!({
+                "level": 4,
+                "urgency": "normal",
+                "nested": [3, { "region": "Europe" }],
+                "fixed": 42,
+            })
+        );
+    }
+
+    #[test]
+    fn inline_placeholders_are_substituted() {
+        let template = Template::new(json!({
+            "text": "{{region}} went from {{old}} to {{new}} ({{urgency}})",
+        }))
+        .unwrap();
+        assert_eq!(
+            template.render(&event()),
+            json!({ "text": "Europe went from 3 to 4 (normal)" })
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let error = Template::new(json!({ "text": "{{progress}}" })).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("unknown placeholder `{{progress}}`"));
+
+        let error = Template::new(json!([{ "nested": "at {{levle}}" }])).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("unknown placeholder `{{levle}}`"));
+    }
+
+    #[test]
+    fn default_template_has_every_field() {
+        let event = event();
+        assert_eq!(
+            Template::default().render(&event),
+            Value::Object(event.fields())
+        );
+    }
+
+    #[test]
+    fn stale_placeholder() {
+        let template =
+            Template::new(json!({ "stale": "{{stale}}", "text": "stale: {{stale}}" })).unwrap();
+
+        let mut event = event();
+        event.soften(2 * 60 * 60);
+        assert_eq!(
+            template.render(&event),
+            json!({ "stale": true, "text": "stale: true" })
+        );
+    }
+
+    #[test]
+    fn chat_formats_credit_the_sources() {
+        let credits = "diablo2.io and d2runewizard.com".to_owned();
+        let event = event();
+
+        let discord = Format::Discord {
+            mentions: Vec::new(),
+            credits: credits.clone(),
+        };
+        assert_eq!(
+            discord.render(&event)["embeds"][0]["footer"]["text"],
+            "Data courtesy of diablo2.io and d2runewizard.com"
+        );
+
+        let slack = Format::Slack {
+            mentions: Vec::new(),
+            credits,
+        };
+        assert_eq!(
+            slack.render(&event)["attachments"][0]["blocks"][2]["elements"][0]["text"],
+            "Data courtesy of diablo2.io and d2runewizard.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_rendered_template() {
+        let (url, mut received) = stand_in(StatusCode::OK, Duration::ZERO);
+        let template = Template::new(json!({
+            "content": "{{title}}",
+            "level": "{{new}}",
+        }))
+        .unwrap();
+        let webhook = webhook(&url, Format::Template(template), 0, Duration::from_secs(5));
+
+        let event = event();
+        webhook.notify(&event).unwrap();
+
+        let body = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(body, json!({ "content": event.title(), "level": 4 }));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, mut received) = stand_in(StatusCode::BAD_GATEWAY, Duration::ZERO);
+        let webhook = webhook(
+            &url,
+            Format::Template(Template::default()),
+            2,
+            Duration::from_secs(5),
+        );
+
+        let request = webhook.request(&url, &json!({}));
+        let error = post(request, webhook.retries, "test").await.unwrap_err();
+        let error = error.downcast_ref::<reqwest::Error>().unwrap();
+        assert_eq!(error.status(), Some(reqwest::StatusCode::BAD_GATEWAY));
+        assert!(!error.to_string().contains(&url));
+
+        // the first attempt and two retries
+        let mut attempts = 0;
+        while received.try_recv().is_ok() {
+            attempts += 1;
+        }
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn applies_timeout() {
+        let (url, _received) = stand_in(StatusCode::OK, Duration::from_secs(10));
+        let webhook = webhook(
+            &url,
+            Format::Template(Template::default()),
+            0,
+            Duration::from_millis(200),
+        );
+
+        let start = tokio::time::Instant::now();
+        let request = webhook.request(&url, &json!({}));
+        let error = post(request, webhook.retries, "test").await.unwrap_err();
+
+        assert!(error.downcast_ref::<reqwest::Error>().unwrap().is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
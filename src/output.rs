@@ -0,0 +1,112 @@
+use crate::history::format_time;
+use crate::Reading;
+use serde_json::json;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Formats `--oneshot` can write its results to stdout in
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Format {
+    Json,
+    Csv,
+    Table,
+    Plain,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "table" => Ok(Format::Table),
+            "plain" => Ok(Format::Plain),
+            _ => Err(format!(
+                "invalid format `{}`: expected json, csv, table or plain",
+                s
+            )),
+        }
+    }
+}
+
+/// Write `readings` to `out` in the given format
+pub fn write(format: Format, readings: &[Reading], out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            let readings: Vec<_> = readings
+                .iter()
+                .map(|reading| {
+                    json!({
+                        "realm": reading.realm.id(),
+                        "realm_name": reading.realm.to_string(),
+                        "region": reading.region.name(),
+                        "region_code": reading.region.code(),
+                        "level": reading.level,
+                        "reported_at": reading.reported_at,
+                        "stale": reading.stale,
+                        "estimate": reading.estimate,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &readings)?;
+            writeln!(out)
+        }
+        Format::Csv => {
+            writeln!(out, "realm,region,region_code,level,reported_at,stale")?;
+            for reading in readings {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    reading.realm.id(),
+                    reading.region.name(),
+                    reading.region.code(),
+                    reading.level,
+                    reading
+                        .reported_at
+                        .map(|t| t.to_string())
+                        .unwrap_or_default(),
+                    reading.stale,
+                )?;
+            }
+            Ok(())
+        }
+        Format::Table => {
+            writeln!(
+                out,
+                "{:<14} {:<10} {:>4} {:>5}  REPORTED",
+                "REALM", "REGION", "CODE", "LEVEL"
+            )?;
+            for reading in readings {
+                writeln!(
+                    out,
+                    "{:<14} {:<10} {:>4} {:>3}/6  {}{}",
+                    reading.realm.id(),
+                    reading.region.name(),
+                    reading.region.code(),
+                    reading.level,
+                    reading.reported_at.map(format_time).unwrap_or_default(),
+                    if reading.stale { " (stale)" } else { "" },
+                )?;
+            }
+            Ok(())
+        }
+        Format::Plain => {
+            for reading in readings {
+                write!(
+                    out,
+                    "[{}] Progress for {}: {}/6{}",
+                    reading.realm,
+                    reading.region.name(),
+                    reading.level,
+                    if reading.stale { " (stale)" } else { "" }
+                )?;
+                match &reading.estimate {
+                    Some(estimate) => writeln!(out, ", {}", estimate)?,
+                    None => writeln!(out)?,
+                }
+            }
+            Ok(())
+        }
+    }
+}
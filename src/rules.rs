@@ -0,0 +1,174 @@
+use crate::notifier::{Event, EventKind};
+use crate::{Realm, Region};
+use serde::Deserialize;
+
+/// A filter for which status changes are worth a notification
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// realms the rule applies to (default: all)
+    pub realms: Option<Vec<Realm>>,
+
+    /// regions the rule applies to (default: all)
+    pub regions: Option<Vec<Region>>,
+
+    /// lowest level to notify about
+    #[serde(default = "default_min_level")]
+    pub min_level: i32,
+
+    /// only notify when the level goes up
+    #[serde(default)]
+    pub rising_only: bool,
+}
+
+fn default_min_level() -> i32 {
+    1
+}
+
+impl Rule {
+    pub fn matches(&self, event: &Event) -> bool {
+        if let Some(realms) = &self.realms {
+            if !realms.contains(&event.realm) {
+                return false;
+            }
+        }
+
+        if let (Some(regions), Some(region)) = (&self.regions, event.region) {
+            if !regions.contains(&region) {
+                return false;
+            }
+        }
+
+        // a walk is judged by the level DClone walked at, not the level it was reset to
+        if event.kind == EventKind::Walk {
+            return event.old >= self.min_level;
+        }
+
+        if self.rising_only && event.new <= event.old {
+            return false;
+        }
+
+        event.new >= self.min_level
+    }
+}
+
+/// Whether `event` should be sent to the notifiers.
+///
+/// Without any rules, every event is. Rules are about progress, so events about the
+/// tracker itself are always sent.
+pub fn allows(rules: &[Rule], event: &Event) -> bool {
+    event.is_health() || rules.is_empty() || rules.iter().any(|rule| rule.matches(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HC: Realm = Realm {
+        ladder: false,
+        hardcore: true,
+    };
+
+    fn rule(min_level: i32) -> Rule {
+        Rule {
+            realms: None,
+            regions: None,
+            min_level,
+            rising_only: false,
+        }
+    }
+
+    fn change(old: i32, new: i32) -> Event {
+        Event::new(Realm::default(), Region::Europe, old, new).unwrap()
+    }
+
+    #[test]
+    fn min_level() {
+        assert!(rule(4).matches(&change(3, 4)));
+        assert!(rule(4).matches(&change(5, 4)));
+        assert!(!rule(4).matches(&change(2, 3)));
+    }
+
+    #[test]
+    fn rising_only() {
+        let rule = Rule {
+            rising_only: true,
+            ..rule(1)
+        };
+        assert!(rule.matches(&change(2, 3)));
+        assert!(!rule.matches(&change(3, 2)));
+        assert!(!rule.matches(&change(3, 3)));
+    }
+
+    #[test]
+    fn walks_are_judged_by_the_old_level() {
+        let walk = change(6, 1);
+        assert_eq!(walk.kind, EventKind::Walk);
+        assert!(rule(6).matches(&walk));
+
+        let early = change(5, 1);
+        assert!(!rule(6).matches(&early));
+
+        // a walk is a reset, but still worth knowing about when only rises are
+        let rule = Rule {
+            rising_only: true,
+            ..rule(5)
+        };
+        assert!(rule.matches(&walk));
+    }
+
+    #[test]
+    fn region_and_realm_filters() {
+        let europe = Rule {
+            regions: Some(vec![Region::Europe]),
+            ..rule(1)
+        };
+        assert!(europe.matches(&change(1, 2)));
+        assert!(!europe.matches(&Event::new(Realm::default(), Region::Asia, 1, 2).unwrap()));
+
+        let hardcore = Rule {
+            realms: Some(vec![HC]),
+            ..rule(1)
+        };
+        assert!(!hardcore.matches(&change(1, 2)));
+        assert!(hardcore.matches(&Event::new(HC, Region::Europe, 1, 2).unwrap()));
+    }
+
+    #[test]
+    fn allows() {
+        let rules = [
+            rule(5),
+            Rule {
+                regions: Some(vec![Region::Asia]),
+                ..rule(1)
+            },
+        ];
+
+        assert!(super::allows(&[], &change(1, 2)));
+        assert!(super::allows(&rules, &change(4, 5)));
+        assert!(super::allows(
+            &rules,
+            &Event::new(Realm::default(), Region::Asia, 1, 2).unwrap()
+        ));
+        assert!(!super::allows(&rules, &change(1, 2)));
+
+        // health events are about the tracker, whatever the rules say
+        let strict = [Rule {
+            realms: Some(vec![HC]),
+            regions: Some(vec![Region::Asia]),
+            ..rule(6)
+        }];
+        assert!(super::allows(
+            &strict,
+            &Event::degraded(Realm::default(), 3, "timeout")
+        ));
+        assert!(super::allows(
+            &strict,
+            &Event::recovered(Realm::default(), 3)
+        ));
+        assert!(super::allows(
+            &strict,
+            &Event::conflict(Realm::default(), Region::Europe, "2/6 or 4/6".to_owned())
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::history::{self, History};
+use crate::metrics::Metrics;
+use crate::notifier::Event;
+use crate::tracker::Snapshot;
+use crate::{state, Realm, Region, Timestamp};
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// How many history records are returned if the client doesn't ask for a limit
+const DEFAULT_HISTORY_LIMIT: u32 = 1000;
+
+#[derive(Clone)]
+struct AppState {
+    snapshots: watch::Receiver<Snapshot>,
+    metrics: Metrics,
+    events: broadcast::Sender<Event>,
+}
+
+/// Serve the status API on `addr`, in the background
+pub fn spawn(
+    addr: SocketAddr,
+    snapshots: watch::Receiver<Snapshot>,
+    metrics: Metrics,
+    events: broadcast::Sender<Event>,
+) -> Result<JoinHandle<()>> {
+    let app = Router::new()
+        .route("/status", get(status))
+        .route("/history", get(history))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics_text))
+        .route("/events", get(event_stream))
+        .route("/ws", get(websocket))
+        .with_state(AppState {
+            snapshots,
+            metrics,
+            events,
+        });
+
+    let server = axum::Server::try_bind(&addr)?.serve(app.into_make_service());
+    log::info!("Serving status API on http://{}", server.local_addr());
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("Status API failed: {}", e);
+        }
+    }))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Snapshot> {
+    Json(state.snapshots.borrow().clone())
+}
+
+/// Stream change events as Server-Sent Events, starting with a full snapshot
+async fn event_stream(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = std::result::Result<sse::Event, Infallible>>> {
+    // subscribe before taking the snapshot, so no change falls in between
+    let receiver = state.events.subscribe();
+    let snapshot = state.snapshots.borrow().clone();
+
+    let snapshot = sse::Event::default()
+        .event("snapshot")
+        .json_data(&snapshot)
+        .unwrap_or_default();
+
+    let changes = BroadcastStream::new(receiver).filter_map(|event| match event {
+        Ok(event) => Some(
+            sse::Event::default()
+                .event("change")
+                .json_data(event.to_json())
+                .unwrap_or_default(),
+        ),
+        Err(BroadcastStreamRecvError::Lagged(n)) => {
+            log::warn!("Event stream client lagged behind, skipped {} events", n);
+            None
+        }
+    });
+
+    let stream = tokio_stream::once(snapshot).chain(changes).map(Ok);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Push change events over a WebSocket, starting with a full snapshot
+async fn websocket(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| push_events(socket, state))
+}
+
+async fn push_events(mut socket: WebSocket, state: AppState) {
+    let mut receiver = state.events.subscribe();
+    let snapshot = state.snapshots.borrow().clone();
+
+    let message = json!({ "type": "snapshot", "data": snapshot });
+    if send_json(&mut socket, &message).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("WebSocket client lagged behind, skipped {} events", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let message = json!({ "type": "change", "data": event.to_json() });
+                if send_json(&mut socket, &message).await.is_err() {
+                    break;
+                }
+            }
+
+            message = socket.recv() => {
+                // clients aren't expected to say anything, but we need to notice them leaving
+                match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
+
+async fn send_json(socket: &mut WebSocket, value: &Value) -> std::result::Result<(), axum::Error> {
+    socket.send(Message::Text(value.to_string())).await
+}
+
+async fn metrics_text(State(state): State<AppState>) -> Response {
+    let content_type = [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)];
+    (content_type, state.metrics.render()).into_response()
+}
+
+async fn health(State(state): State<AppState>) -> Response {
+    let snapshot = state.snapshots.borrow().clone();
+    let now = state::now();
+
+    // give the tracker a few attempts before declaring it unhealthy
+    let grace = 3 * snapshot.interval;
+    let failing: Vec<Realm> = snapshot
+        .realms
+        .iter()
+        .filter(|r| {
+            let since = r.last_success.unwrap_or(snapshot.started_at);
+            now.saturating_sub(since) > grace
+        })
+        .map(|r| r.realm)
+        .collect();
+
+    if failing.is_empty() {
+        Json(json!({ "status": "ok" })).into_response()
+    } else {
+        let body = json!({ "status": "unhealthy", "failing": failing });
+        (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    /// comma separated realms, e.g. `sc-ladder,hc-ladder`
+    realm: Option<String>,
+    /// comma separated regions
+    region: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<u32>,
+    #[serde(default)]
+    observations: bool,
+}
+
+async fn history(State(state): State<AppState>, Query(query): Query<HistoryQuery>) -> Response {
+    let path = match state.snapshots.borrow().history_file.clone() {
+        Some(path) => path,
+        None => return error(StatusCode::NOT_FOUND, "history is not recorded".to_owned()),
+    };
+
+    if !path.exists() {
+        return error(StatusCode::NOT_FOUND, "no history recorded".to_owned());
+    }
+
+    let filter = match filter(&query) {
+        Ok(filter) => filter,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
+
+    let observations = query.observations;
+    let result = tokio::task::spawn_blocking(move || {
+        History::open_read_only(&path)?.query(&filter, observations)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(records)) => Json(records).into_response(),
+        Ok(Err(e)) => error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn filter(query: &HistoryQuery) -> std::result::Result<history::Filter, String> {
+    Ok(history::Filter {
+        realms: list::<Realm>(&query.realm)?,
+        regions: list::<Region>(&query.region)?,
+        since: query
+            .since
+            .as_deref()
+            .map(Timestamp::from_str)
+            .transpose()?
+            .map(|t| t.0),
+        until: query
+            .until
+            .as_deref()
+            .map(Timestamp::from_str)
+            .transpose()?
+            .map(|t| t.0),
+        limit: Some(query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT)),
+    })
+}
+
+fn list<T: FromStr<Err = String>>(value: &Option<String>) -> std::result::Result<Vec<T>, String> {
+    match value {
+        Some(value) => value.split(',').map(|v| v.trim().parse()).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
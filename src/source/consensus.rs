@@ -0,0 +1,247 @@
+use super::{Level, Progress};
+use crate::Region;
+use std::fmt;
+
+/// The progress the sources of a realm settled on, and where they disagreed
+#[derive(Debug, Default)]
+pub struct Consensus {
+    pub progress: Vec<Progress>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Sources that reported different levels for the same region
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub region: Region,
+    /// each source's name and the level it reported
+    pub reports: Vec<(String, Level)>,
+    /// the source that was trusted anyway, because its report was recent enough
+    pub trusted: Option<String>,
+}
+
+impl Conflict {
+    /// How many levels apart the sources are
+    pub fn spread(&self) -> i32 {
+        let levels = self.reports.iter().map(|(_, level)| level.get());
+        let max = levels.clone().max().unwrap_or_default();
+        let min = levels.min().unwrap_or_default();
+        max - min
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (source, level)) in self.reports.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} according to {}", level, source)?;
+        }
+
+        match &self.trusted {
+            Some(source) => write!(f, "; going with {}, which was reported recently", source),
+            None => write!(f, "; ignoring it until the sources agree"),
+        }
+    }
+}
+
+/// Cross-check what each source reported for a realm.
+///
+/// A region's level is taken when all sources that reported it agree. When they
+/// don't, the most recent report is taken if it's at most `max_age` seconds old
+/// at `now`, and otherwise the region is left out.
+pub fn reconcile(reports: &[(&str, Vec<Progress>)], max_age: u64, now: u64) -> Consensus {
+    let mut consensus = Consensus::default();
+
+    for region in Region::ALL {
+        let reported: Vec<(&str, &Progress)> = reports
+            .iter()
+            .filter_map(|(source, progress)| {
+                let progress = progress.iter().find(|p| p.region == region)?;
+                Some((*source, progress))
+            })
+            .collect();
+
+        // the most recent report, or the first source's if they're equally recent
+        let Some(&(latest_source, latest)) = reported
+            .iter()
+            .rev()
+            .max_by_key(|(_, progress)| progress.reported_at)
+        else {
+            continue;
+        };
+
+        if reported.iter().all(|(_, p)| p.level == latest.level) {
+            consensus.progress.push(latest.clone());
+            continue;
+        }
+
+        let fresh = latest
+            .reported_at
+            .is_some_and(|t| now.saturating_sub(t) <= max_age);
+
+        if fresh {
+            consensus.progress.push(latest.clone());
+        }
+
+        consensus.conflicts.push(Conflict {
+            region,
+            reports: reported
+                .iter()
+                .map(|(source, p)| (source.to_string(), p.level))
+                .collect(),
+            trusted: fresh.then(|| latest_source.to_owned()),
+        });
+    }
+
+    consensus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Realm;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn progress(region: Region, level: i64, reported_at: Option<u64>) -> Progress {
+        Progress {
+            realm: Realm::default(),
+            region,
+            level: Level::new(level).unwrap(),
+            reported_at,
+        }
+    }
+
+    fn levels(consensus: &Consensus) -> Vec<(Region, i32)> {
+        consensus
+            .progress
+            .iter()
+            .map(|progress| (progress.region, progress.level.get()))
+            .collect()
+    }
+
+    #[test]
+    fn sources_that_agree() {
+        let reports = [
+            (
+                "a",
+                vec![
+                    progress(Region::Americas, 2, Some(NOW - 600)),
+                    progress(Region::Europe, 3, None),
+                ],
+            ),
+            (
+                "b",
+                vec![
+                    progress(Region::Americas, 2, Some(NOW - 60)),
+                    progress(Region::Europe, 3, Some(NOW - 6000)),
+                ],
+            ),
+        ];
+
+        let consensus = reconcile(&reports, 300, NOW);
+        assert_eq!(
+            levels(&consensus),
+            [(Region::Americas, 2), (Region::Europe, 3)]
+        );
+        assert!(consensus.conflicts.is_empty());
+        assert_eq!(consensus.progress[0].reported_at, Some(NOW - 60));
+    }
+
+    #[test]
+    fn trusts_a_fresh_report() {
+        let reports = [
+            ("a", vec![progress(Region::Europe, 2, Some(NOW - 900))]),
+            ("b", vec![progress(Region::Europe, 4, Some(NOW - 120))]),
+        ];
+
+        let consensus = reconcile(&reports, 300, NOW);
+        assert_eq!(levels(&consensus), [(Region::Europe, 4)]);
+
+        let [conflict] = &consensus.conflicts[..] else {
+            panic!("expected one conflict, got {:?}", consensus.conflicts);
+        };
+        assert_eq!(conflict.region, Region::Europe);
+        assert_eq!(conflict.trusted.as_deref(), Some("b"));
+        assert_eq!(
+            conflict.reports,
+            [
+                ("a".to_owned(), Level::new(2).unwrap()),
+                ("b".to_owned(), Level::new(4).unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_disagreement_without_a_fresh_report() {
+        let reports = [
+            ("a", vec![progress(Region::Asia, 2, Some(NOW - 900))]),
+            ("b", vec![progress(Region::Asia, 3, Some(NOW - 600))]),
+        ];
+
+        let consensus = reconcile(&reports, 300, NOW);
+        assert!(consensus.progress.is_empty());
+        assert_eq!(consensus.conflicts.len(), 1);
+        assert_eq!(consensus.conflicts[0].trusted, None);
+
+        // without any timestamps, no report is fresh either
+        let reports = [
+            ("a", vec![progress(Region::Asia, 2, None)]),
+            ("b", vec![progress(Region::Asia, 3, None)]),
+        ];
+        let consensus = reconcile(&reports, 300, NOW);
+        assert!(consensus.progress.is_empty());
+        assert_eq!(consensus.conflicts[0].trusted, None);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_source() {
+        let reports = [
+            ("a", vec![progress(Region::Americas, 5, Some(NOW - 30))]),
+            ("b", vec![progress(Region::Americas, 4, Some(NOW - 30))]),
+        ];
+
+        let consensus = reconcile(&reports, 300, NOW);
+        assert_eq!(levels(&consensus), [(Region::Americas, 5)]);
+        assert_eq!(consensus.conflicts[0].trusted.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn region_missing_from_a_source() {
+        let reports = [
+            (
+                "a",
+                vec![
+                    progress(Region::Americas, 1, Some(NOW - 900)),
+                    progress(Region::Asia, 3, Some(NOW - 900)),
+                ],
+            ),
+            ("b", vec![progress(Region::Americas, 1, Some(NOW - 60))]),
+        ];
+
+        let consensus = reconcile(&reports, 300, NOW);
+        assert_eq!(
+            levels(&consensus),
+            [(Region::Americas, 1), (Region::Asia, 3)]
+        );
+        assert!(consensus.conflicts.is_empty());
+    }
+
+    #[test]
+    fn spread() {
+        let conflict = |levels: &[i64]| Conflict {
+            region: Region::Europe,
+            reports: levels
+                .iter()
+                .map(|&level| ("source".to_owned(), Level::new(level).unwrap()))
+                .collect(),
+            trusted: None,
+        };
+
+        assert_eq!(conflict(&[2, 3]).spread(), 1);
+        assert_eq!(conflict(&[5, 1, 3]).spread(), 4);
+        assert_eq!(conflict(&[4, 4]).spread(), 0);
+        assert_eq!(conflict(&[]).spread(), 0);
+    }
+}
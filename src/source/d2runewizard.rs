@@ -0,0 +1,365 @@
+use super::{integer, invalid, parse_entries, Level, Progress, SchemaError, Source};
+use crate::client::{Client, FetchError};
+use crate::{Realm, Region};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Where d2runewizard serves the progress of all realms
+pub const DEFAULT_URL: &str = "https://d2runewizard.com/api/diablo-clone-progress/all";
+
+/// The DClone progress API of d2runewizard
+pub struct D2runewizard {
+    name: String,
+    url: String,
+    token: Option<String>,
+    /// how long a response may be shared between realms
+    share_for: Duration,
+    shared: Mutex<Option<Shared>>,
+}
+
+/// A response covering every realm, kept for the realms that haven't used it yet
+struct Shared {
+    response: Value,
+    fetched_at: Instant,
+    used_by: HashSet<Realm>,
+}
+
+impl D2runewizard {
+    /// The API at `url`, sharing each response between realms for up to `share_for`
+    pub fn new(name: String, url: String, token: Option<String>, share_for: Duration) -> Self {
+        D2runewizard {
+            name,
+            url,
+            token,
+            share_for,
+            shared: Mutex::new(None),
+        }
+    }
+
+    fn url(&self) -> String {
+        match (reqwest::Url::parse(&self.url), &self.token) {
+            (Ok(mut url), Some(token)) => {
+                url.query_pairs_mut().append_pair("token", token);
+                url.into()
+            }
+            _ => self.url.clone(),
+        }
+    }
+
+    /// The last response, if it's recent and `realm` hasn't used it yet.
+    ///
+    /// This way every realm is queried with one request per round, while a realm
+    /// that's queried again gets a new response.
+    fn shared(&self, realm: Realm) -> Option<Value> {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = shared
+            .as_mut()
+            .filter(|shared| shared.fetched_at.elapsed() < self.share_for)?;
+        shared
+            .used_by
+            .insert(realm)
+            .then(|| shared.response.clone())
+    }
+
+    /// Keep `response` for the other realms, `realm` having used it
+    fn share(&self, realm: Realm, response: &Value) {
+        *self.shared.lock().unwrap() = Some(Shared {
+            response: response.clone(),
+            fetched_at: Instant::now(),
+            used_by: HashSet::from([realm]),
+        });
+    }
+}
+
+#[async_trait]
+impl Source for D2runewizard {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch(&self, client: &Client, realm: Realm) -> Result<Vec<Progress>, FetchError> {
+        let response = match self.shared(realm) {
+            Some(response) => {
+                log::debug!("[{}] Using the response fetched for another realm", realm);
+                response
+            }
+            None => {
+                // the URL carries the token, keep it out of error messages
+                let response: Value = client
+                    .get_json(&self.name, &self.url())
+                    .await
+                    .map_err(FetchError::without_url)?;
+                log::debug!("[{}] Received response: {}", realm, response);
+                self.share(realm, &response);
+                response
+            }
+        };
+
+        Ok(parse_response(realm, &response)?)
+    }
+}
+
+/// The entries of a response that are about `realm`, the response covers every realm.
+///
+/// Errors like an invalid token come back without any servers, so that's an error too.
+fn parse_response(realm: Realm, response: &Value) -> Result<Vec<Progress>, SchemaError> {
+    let entries = match response.get("servers") {
+        Some(Value::Array(entries)) => entries,
+        _ => return Err(SchemaError::MissingField("servers")),
+    };
+
+    let progress: Vec<Progress> = parse_entries(realm, entries, parse)?
+        .into_iter()
+        .filter(|progress| progress.realm == realm)
+        .collect();
+
+    if progress.is_empty() {
+        return Err(SchemaError::NoEntries {
+            skipped: entries.len(),
+        });
+    }
+
+    Ok(progress)
+}
+
+/// Validate a single entry of a response
+fn parse(entry: &Value) -> Result<Progress, SchemaError> {
+    if !entry.is_object() {
+        return Err(SchemaError::NotAnObject(entry.clone()));
+    }
+
+    let (realm, region) = match entry.get("server") {
+        None | Some(Value::Null) => return Err(SchemaError::MissingField("server")),
+        Some(value) => value
+            .as_str()
+            .and_then(server)
+            .ok_or_else(|| invalid("server", value))?,
+    };
+
+    let level = match entry.get("progress") {
+        None | Some(Value::Null) => return Err(SchemaError::MissingField("progress")),
+        Some(value) => integer(value)
+            .and_then(Level::new)
+            .ok_or_else(|| invalid("progress", value))?,
+    };
+
+    let reported_at = match entry
+        .get("lastUpdate")
+        .and_then(|update| update.get("seconds"))
+    {
+        None | Some(Value::Null) => None,
+        Some(value) => Some(
+            integer(value)
+                .and_then(|t| u64::try_from(t).ok())
+                .ok_or_else(|| invalid("lastUpdate.seconds", value))?,
+        ),
+    };
+
+    Ok(Progress {
+        realm,
+        region,
+        level,
+        reported_at,
+    })
+}
+
+/// Realm and region of a server name like `ladderSoftcoreEurope` or `nonLadderHardcoreAsia`
+fn server(name: &str) -> Option<(Realm, Region)> {
+    let (ladder, rest) = if let Some(rest) = name.strip_prefix("nonLadder") {
+        (false, rest)
+    } else {
+        (true, name.strip_prefix("ladder")?)
+    };
+
+    let (hardcore, region) = if let Some(region) = rest.strip_prefix("Hardcore") {
+        (true, region)
+    } else {
+        (false, rest.strip_prefix("Softcore")?)
+    };
+
+    let region = match region {
+        "Americas" => Region::Americas,
+        "Europe" => Region::Europe,
+        "Asia" => Region::Asia,
+        _ => return None,
+    };
+
+    Some((Realm { ladder, hardcore }, region))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &str = include_str!("fixtures/d2runewizard.json");
+    const MALFORMED: &str = include_str!("fixtures/d2runewizard-malformed.json");
+
+    fn levels(realm: Realm, fixture: &str) -> Vec<(Region, i32, Option<u64>)> {
+        let response: Value = serde_json::from_str(fixture).unwrap();
+        parse_response(realm, &response)
+            .unwrap()
+            .into_iter()
+            .map(|progress| {
+                assert_eq!(progress.realm, realm);
+                (progress.region, progress.level.get(), progress.reported_at)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn server_names() {
+        let realm = |ladder, hardcore| Realm { ladder, hardcore };
+
+        assert_eq!(
+            server("ladderSoftcoreAmericas"),
+            Some((realm(true, false), Region::Americas))
+        );
+        assert_eq!(
+            server("ladderHardcoreEurope"),
+            Some((realm(true, true), Region::Europe))
+        );
+        assert_eq!(
+            server("nonLadderSoftcoreEurope"),
+            Some((realm(false, false), Region::Europe))
+        );
+        assert_eq!(
+            server("nonLadderHardcoreAsia"),
+            Some((realm(false, true), Region::Asia))
+        );
+
+        assert_eq!(server("nonladderHardcoreAsia"), None);
+        assert_eq!(server("ladderHardcore"), None);
+        assert_eq!(server("SoftcoreEurope"), None);
+        assert_eq!(server("ladderSoftcoreMars"), None);
+    }
+
+    #[test]
+    fn keeps_only_the_requested_realm() {
+        let realm = Realm {
+            ladder: true,
+            hardcore: false,
+        };
+        assert_eq!(
+            levels(realm, RESPONSE),
+            [
+                (Region::Americas, 4, Some(1697371103)),
+                (Region::Europe, 1, Some(1697371006)),
+                (Region::Asia, 5, Some(1697370909)),
+            ]
+        );
+
+        let realm = Realm {
+            ladder: false,
+            hardcore: true,
+        };
+        assert_eq!(
+            levels(realm, RESPONSE),
+            [
+                (Region::Americas, 1, Some(1697370230)),
+                (Region::Europe, 4, Some(1697370133)),
+                (Region::Asia, 2, Some(1697370036)),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_malformed_entries() {
+        let realm = Realm {
+            ladder: false,
+            hardcore: true,
+        };
+        assert_eq!(
+            levels(realm, MALFORMED),
+            [
+                (Region::Asia, 3, Some(1697370036)),
+                (Region::Americas, 1, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        let entry = serde_json::json!({
+            "server": "ladderSoftcoreAsia",
+            "progress": 2,
+            "lastUpdate": { "seconds": "yesterday" },
+        });
+        assert_eq!(
+            parse(&entry).unwrap_err(),
+            SchemaError::InvalidField {
+                field: "lastUpdate.seconds",
+                value: "yesterday".into(),
+            }
+        );
+
+        let entry = serde_json::json!({ "server": "ladderSoftcoreAsia" });
+        assert_eq!(
+            parse(&entry).unwrap_err(),
+            SchemaError::MissingField("progress")
+        );
+    }
+
+    #[test]
+    fn shares_responses_once_per_realm() {
+        let source = D2runewizard::new(
+            "d2runewizard".to_owned(),
+            DEFAULT_URL.to_owned(),
+            None,
+            Duration::from_secs(90),
+        );
+        let sc = Realm::default();
+        let hc = Realm {
+            ladder: false,
+            hardcore: true,
+        };
+        let response: Value = serde_json::from_str(RESPONSE).unwrap();
+
+        assert_eq!(source.shared(sc), None);
+        source.share(sc, &response);
+
+        assert_eq!(source.shared(hc), Some(response.clone()));
+        assert_eq!(source.shared(hc), None);
+        assert_eq!(source.shared(sc), None);
+    }
+
+    #[test]
+    fn shared_responses_expire() {
+        let source = D2runewizard::new(
+            "d2runewizard".to_owned(),
+            DEFAULT_URL.to_owned(),
+            None,
+            Duration::ZERO,
+        );
+        let response: Value = serde_json::from_str(RESPONSE).unwrap();
+
+        source.share(Realm::default(), &response);
+        let hc = Realm {
+            ladder: false,
+            hardcore: true,
+        };
+        assert_eq!(source.shared(hc), None);
+    }
+
+    #[test]
+    fn response_without_servers() {
+        let response = serde_json::json!({ "error": "invalid token" });
+        assert_eq!(
+            parse_response(Realm::default(), &response).unwrap_err(),
+            SchemaError::MissingField("servers")
+        );
+    }
+
+    #[test]
+    fn response_without_the_realm() {
+        let response = serde_json::json!({
+            "servers": [{ "server": "ladderHardcoreAsia", "progress": 2 }],
+        });
+        assert_eq!(
+            parse_response(Realm::default(), &response).unwrap_err(),
+            SchemaError::NoEntries { skipped: 1 }
+        );
+    }
+}
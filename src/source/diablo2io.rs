@@ -0,0 +1,231 @@
+use super::{integer, invalid, parse_entries, Level, Progress, SchemaError, Source};
+use crate::client::{Client, FetchError};
+use crate::{Realm, Region};
+use async_trait::async_trait;
+use serde_json::Value;
+
+/// Where diablo2.io serves its DClone API
+pub const DEFAULT_URL: &str = "https://diablo2.io/dclone_api.php";
+
+/// The DClone API of diablo2.io, or anything serving the same format
+pub struct Diablo2io {
+    name: String,
+    url: String,
+}
+
+impl Diablo2io {
+    pub fn new(name: String, url: String) -> Self {
+        Diablo2io { name, url }
+    }
+
+    fn url(&self, realm: Realm) -> String {
+        let ladder = if realm.ladder { 1 } else { 2 };
+        let hardcore = if realm.hardcore { 1 } else { 2 };
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        format!("{}{}ladder={}&hc={}", self.url, separator, ladder, hardcore)
+    }
+}
+
+#[async_trait]
+impl Source for Diablo2io {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch(&self, client: &Client, realm: Realm) -> Result<Vec<Progress>, FetchError> {
+        let entries: Vec<Value> = client.get_json(&self.name, &self.url(realm)).await?;
+        log::debug!(
+            "[{}] Received response: {}",
+            realm,
+            Value::from(entries.clone())
+        );
+
+        let progress = parse_entries(realm, &entries, |entry| parse(entry, realm))?;
+        Ok(progress)
+    }
+}
+
+/// Validate a single entry of a response for `realm`
+fn parse(entry: &Value, realm: Realm) -> Result<Progress, SchemaError> {
+    if !entry.is_object() {
+        return Err(SchemaError::NotAnObject(entry.clone()));
+    }
+
+    let region = match entry.get("region") {
+        None | Some(Value::Null) => return Err(SchemaError::MissingField("region")),
+        Some(value) => integer(value)
+            .and_then(|code| Region::from_code(&code.to_string()))
+            .ok_or_else(|| invalid("region", value))?,
+    };
+
+    let level = match entry.get("progress") {
+        None | Some(Value::Null) => return Err(SchemaError::MissingField("progress")),
+        Some(value) => integer(value)
+            .and_then(Level::new)
+            .ok_or_else(|| invalid("progress", value))?,
+    };
+
+    let ladder = flag(entry, "ladder")?;
+    let hardcore = flag(entry, "hc")?;
+
+    // older responses may lack the realm fields, so only check them when present
+    if let (Some(ladder), Some(hardcore)) = (ladder, hardcore) {
+        let found = Realm { ladder, hardcore };
+        if found != realm {
+            return Err(SchemaError::WrongRealm {
+                expected: realm,
+                found,
+            });
+        }
+    }
+
+    let reported_at = match entry.get("timestamped") {
+        None | Some(Value::Null) => None,
+        Some(value) => Some(
+            integer(value)
+                .and_then(|t| u64::try_from(t).ok())
+                .ok_or_else(|| invalid("timestamped", value))?,
+        ),
+    };
+
+    Ok(Progress {
+        realm,
+        region,
+        level,
+        reported_at,
+    })
+}
+
+/// `ladder` and `hc` are 1 for yes and 2 for no
+fn flag(entry: &Value, field: &'static str) -> Result<Option<bool>, SchemaError> {
+    let value = match entry.get(field) {
+        None | Some(Value::Null) => return Ok(None),
+        Some(value) => value,
+    };
+
+    match integer(value) {
+        Some(1) => Ok(Some(true)),
+        Some(2) => Ok(Some(false)),
+        _ => Err(invalid(field, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HC_LADDER: Realm = Realm {
+        ladder: true,
+        hardcore: true,
+    };
+
+    #[test]
+    fn levels_as_strings_or_numbers() {
+        let entry = json!({
+            "region": "2",
+            "progress": "4",
+            "ladder": "1",
+            "hc": "1",
+            "timestamped": "1697371103",
+        });
+        let progress = parse(&entry, HC_LADDER).unwrap();
+        assert_eq!(progress.realm, HC_LADDER);
+        assert_eq!(progress.region, Region::Europe);
+        assert_eq!(progress.level.get(), 4);
+        assert_eq!(progress.reported_at, Some(1697371103));
+
+        let entry = json!({ "region": 3, "progress": 6, "timestamped": 1697371103 });
+        let progress = parse(&entry, HC_LADDER).unwrap();
+        assert_eq!(progress.region, Region::Asia);
+        assert_eq!(progress.level.get(), 6);
+        assert_eq!(progress.reported_at, Some(1697371103));
+    }
+
+    #[test]
+    fn levels_out_of_range() {
+        for level in [json!("0"), json!(7)] {
+            let entry = json!({ "region": "1", "progress": level });
+            assert_eq!(
+                parse(&entry, HC_LADDER).unwrap_err(),
+                SchemaError::InvalidField {
+                    field: "progress",
+                    value: level,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn missing_region() {
+        let entry = json!({ "progress": "3" });
+        assert_eq!(
+            parse(&entry, HC_LADDER).unwrap_err(),
+            SchemaError::MissingField("region")
+        );
+
+        let entry = json!({ "region": "4", "progress": "3" });
+        assert_eq!(
+            parse(&entry, HC_LADDER).unwrap_err(),
+            SchemaError::InvalidField {
+                field: "region",
+                value: "4".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn wrong_realm() {
+        let entry = json!({ "region": "1", "progress": "2", "ladder": "2", "hc": "1" });
+        assert_eq!(
+            parse(&entry, HC_LADDER).unwrap_err(),
+            SchemaError::WrongRealm {
+                expected: HC_LADDER,
+                found: Realm {
+                    ladder: false,
+                    hardcore: true,
+                },
+            }
+        );
+
+        let entry = json!({ "region": "1", "progress": "2", "ladder": "3", "hc": "1" });
+        assert_eq!(
+            parse(&entry, HC_LADDER).unwrap_err(),
+            SchemaError::InvalidField {
+                field: "ladder",
+                value: "3".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn bad_timestamp() {
+        for timestamp in [json!("yesterday"), json!(-1)] {
+            let entry = json!({ "region": "1", "progress": "2", "timestamped": timestamp });
+            assert_eq!(
+                parse(&entry, HC_LADDER).unwrap_err(),
+                SchemaError::InvalidField {
+                    field: "timestamped",
+                    value: timestamp,
+                }
+            );
+        }
+
+        let entry = json!({ "region": "1", "progress": "2", "timestamped": null });
+        assert_eq!(parse(&entry, HC_LADDER).unwrap().reported_at, None);
+    }
+
+    #[test]
+    fn response_without_usable_entries() {
+        let entries = [json!({ "region": "1" }), json!("garbage")];
+        let error = parse_entries(HC_LADDER, &entries, |entry| parse(entry, HC_LADDER))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(error, SchemaError::NoEntries { skipped: 2 });
+
+        let error = parse_entries(HC_LADDER, &[], |entry| parse(entry, HC_LADDER))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(error, SchemaError::NoEntries { skipped: 0 });
+    }
+}
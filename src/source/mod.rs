@@ -0,0 +1,280 @@
+use crate::client::{Client, FetchError};
+use crate::config::Config;
+use crate::{Realm, Region};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub mod consensus;
+pub mod d2runewizard;
+pub mod diablo2io;
+
+pub use d2runewizard::D2runewizard;
+pub use diablo2io::Diablo2io;
+
+/// The built-in sources, and the API formats mirrors can serve
+#[derive(Debug, Default, PartialEq, Eq, Hash, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    Diablo2io,
+    D2runewizard,
+}
+
+impl Kind {
+    /// Name of the site the data comes from, for attribution
+    pub fn site(self) -> &'static str {
+        match self {
+            Kind::Diablo2io => "diablo2.io",
+            Kind::D2runewizard => "d2runewizard.com",
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "diablo2io" => Ok(Kind::Diablo2io),
+            "d2runewizard" => Ok(Kind::D2runewizard),
+            _ => Err(format!(
+                "invalid source `{}`: expected diablo2io or d2runewizard",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Kind::Diablo2io => "diablo2io",
+            Kind::D2runewizard => "d2runewizard",
+        };
+        f.write_str(s)
+    }
+}
+
+/// How far DClone has progressed in a region, from 1 to 6
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Level(u8);
+
+impl Level {
+    pub fn new(level: i64) -> Option<Self> {
+        match level {
+            1..=6 => Some(Level(level as u8)),
+            _ => None,
+        }
+    }
+
+    pub fn get(self) -> i32 {
+        self.0 as i32
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/6", self.0)
+    }
+}
+
+/// A region's progress, as reported by a source
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub realm: Realm,
+    pub region: Region,
+    pub level: Level,
+    /// when progress was last reported to the source
+    pub reported_at: Option<u64>,
+}
+
+/// Ways a response or its entries can differ from what a source is known to return
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    /// the entry isn't a JSON object
+    NotAnObject(Value),
+    MissingField(&'static str),
+    /// a field has a type or value that isn't understood
+    InvalidField {
+        field: &'static str,
+        value: Value,
+    },
+    /// the entry is for a different realm than the one queried
+    WrongRealm {
+        expected: Realm,
+        found: Realm,
+    },
+    /// not a single entry of the response could be used, out of `skipped`
+    NoEntries {
+        skipped: usize,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::NotAnObject(value) => write!(f, "expected an object, got {}", value),
+            SchemaError::MissingField(field) => write!(f, "missing field `{}`", field),
+            SchemaError::InvalidField { field, value } => {
+                write!(f, "invalid value for `{}`: {}", field, value)
+            }
+            SchemaError::WrongRealm { expected, found } => {
+                write!(f, "entry is for {} instead of {}", found, expected)
+            }
+            SchemaError::NoEntries { skipped: 0 } => write!(f, "response has no entries"),
+            SchemaError::NoEntries { skipped } => {
+                write!(
+                    f,
+                    "none of the {} entries of the response is usable",
+                    skipped
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Somewhere DClone progress can be queried from
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Name of the source, used in log messages
+    fn name(&self) -> &str;
+
+    /// Query the progress of every region of `realm`.
+    ///
+    /// Entries that don't fit the source's schema are logged and skipped.
+    async fn fetch(&self, client: &Client, realm: Realm) -> Result<Vec<Progress>, FetchError>;
+}
+
+/// All sources, as configured
+pub struct Sources {
+    diablo2io: Diablo2io,
+    d2runewizard: D2runewizard,
+    mirrors: Vec<(String, Box<dyn Source>)>,
+}
+
+impl Sources {
+    pub fn new(config: &Config) -> Self {
+        let d2runewizard = &config.sources.d2runewizard;
+        // d2runewizard answers for every realm at once, which lasts the realms a round
+        let interval = Duration::from_secs(config.interval);
+        let mirrors = config
+            .sources
+            .mirrors
+            .iter()
+            .map(|mirror| {
+                let name = mirror.name.clone();
+                let source: Box<dyn Source> = match mirror.format {
+                    Kind::Diablo2io => Box::new(Diablo2io::new(name.clone(), mirror.url.clone())),
+                    Kind::D2runewizard => Box::new(D2runewizard::new(
+                        name.clone(),
+                        mirror.url.clone(),
+                        mirror.token.clone(),
+                        interval,
+                    )),
+                };
+                (name, source)
+            })
+            .collect();
+
+        Sources {
+            diablo2io: Diablo2io::new(
+                Kind::Diablo2io.site().to_owned(),
+                config.sources.diablo2io.url.clone(),
+            ),
+            d2runewizard: D2runewizard::new(
+                Kind::D2runewizard.site().to_owned(),
+                d2runewizard.url.clone(),
+                d2runewizard.token.clone(),
+                interval,
+            ),
+            mirrors,
+        }
+    }
+
+    /// The sources with the given names, skipping any that aren't configured
+    pub fn select(&self, names: &[String]) -> Vec<&dyn Source> {
+        names.iter().filter_map(|name| self.get(name)).collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn Source> {
+        match name.parse() {
+            Ok(Kind::Diablo2io) => Some(&self.diablo2io),
+            Ok(Kind::D2runewizard) => Some(&self.d2runewizard),
+            Err(_) => self
+                .mirrors
+                .iter()
+                .find(|(mirror, _)| mirror == name)
+                .map(|(_, source)| source.as_ref()),
+        }
+    }
+}
+
+/// Sites the configured realms are tracked with, for attribution
+pub fn credits(config: &Config) -> String {
+    let mut sites: Vec<&str> = Vec::new();
+    for realm in &config.realms {
+        for name in &realm.sources {
+            if let Some(site) = config.sources.kind(name).map(Kind::site) {
+                if !sites.contains(&site) {
+                    sites.push(site);
+                }
+            }
+        }
+    }
+
+    if sites.is_empty() {
+        sites.push(Kind::default().site());
+    }
+
+    sites.join(" and ")
+}
+
+/// Keep the entries of a response that fit the schema, logging the others.
+///
+/// A response without a single usable entry is an error, since nothing can be
+/// tracked from it.
+fn parse_entries<F>(realm: Realm, entries: &[Value], parse: F) -> Result<Vec<Progress>, SchemaError>
+where
+    F: Fn(&Value) -> Result<Progress, SchemaError>,
+{
+    let progress: Vec<Progress> = entries
+        .iter()
+        .filter_map(|entry| match parse(entry) {
+            Ok(progress) => Some(progress),
+            Err(e) => {
+                log::warn!("[{}] Skipping unexpected entry: {}", realm, e);
+                None
+            }
+        })
+        .collect();
+
+    if progress.is_empty() {
+        return Err(SchemaError::NoEntries {
+            skipped: entries.len(),
+        });
+    }
+
+    Ok(progress)
+}
+
+fn invalid(field: &'static str, value: &Value) -> SchemaError {
+    SchemaError::InvalidField {
+        field,
+        value: value.clone(),
+    }
+}
+
+/// Numbers tend to be sent as strings, but plain numbers are accepted too
+fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
@@ -0,0 +1,95 @@
+use crate::config;
+use crate::{Realm, Status};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Tracker state as it is persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// UNIX timestamp (seconds) of when the state was written
+    saved_at: u64,
+    realms: Vec<RealmState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RealmState {
+    realm: Realm,
+    status: Status,
+}
+
+impl State {
+    /// Build a state snapshot from the tracked statuses
+    pub fn new(statuses: &[(Realm, Status)]) -> Self {
+        let realms = statuses
+            .iter()
+            .map(|&(realm, status)| RealmState { realm, status })
+            .collect();
+
+        State {
+            saved_at: now(),
+            realms,
+        }
+    }
+
+    /// Get the saved status of a realm, if there is one
+    pub fn status(&self, realm: Realm) -> Option<Status> {
+        self.realms
+            .iter()
+            .find(|saved| saved.realm == realm)
+            .map(|saved| saved.status)
+    }
+
+    /// Load the state from `path`.
+    ///
+    /// A missing file or a state older than `max_age` yields an empty state.
+    pub fn load(path: &Path, max_age: Duration) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(State::default()),
+            Err(e) => return Err(e).context(format!("reading {}", path.display())),
+        };
+
+        let state: State = serde_json::from_str(&contents)
+            .with_context(|| format!("parsing {}", path.display()))?;
+
+        let age = now().saturating_sub(state.saved_at);
+        if age > max_age.as_secs() {
+            log::info!("Discarding state saved {} seconds ago", age);
+            return Ok(State::default());
+        }
+
+        Ok(state)
+    }
+
+    /// Write the state to `path`, replacing any previous state
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+
+        // write to a temporary file first, so we never leave a truncated state behind
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("writing {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Default location of the state file, following the XDG base directory spec
+pub fn default_path() -> Result<PathBuf> {
+    Ok(config::xdg_dir("XDG_STATE_HOME", ".local/state")?.join("state.json"))
+}
+
+/// Current UNIX timestamp (seconds)
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,665 @@
+use crate::client::{Client, FetchError};
+use crate::config::{Config, RealmConfig, StalePolicy};
+use crate::history::{self, History, Observation};
+use crate::metrics::Metrics;
+use crate::notifier::{self, Event, Notifiers};
+use crate::source::{self, consensus, Sources};
+use crate::state::{self, State};
+use crate::{eta, rules, Realm, Region, Status};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+/// Delay before the first retry of a failed query, doubled for every further one
+const RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// The daemon's view of the configured realms
+pub struct Tracker {
+    config: Config,
+    statuses: Vec<(Realm, Status)>,
+    notifiers: Notifiers,
+    sources: Sources,
+    state_path: PathBuf,
+    /// Resets that still need to be seen again before they count as a walk
+    pending_walks: Vec<(Realm, Region)>,
+    history: Option<History>,
+    /// whether state and history are written, or only read
+    persist: bool,
+    fetches: HashMap<Realm, Fetch>,
+    started_at: u64,
+    metrics: Metrics,
+    events: broadcast::Sender<Event>,
+    /// When each region last changed, as far as this run has seen
+    changed_at: HashMap<(Realm, Region), u64>,
+    /// Regions whose sources disagree by more than a level, already alerted about
+    conflicts: HashSet<(Realm, Region)>,
+    /// When progress of each region was last reported to the source
+    reported_at: HashMap<(Realm, Region), u64>,
+    /// Regions that haven't had a new report in a while
+    stale: HashSet<(Realm, Region)>,
+}
+
+/// Outcome of the most recent queries for a realm
+#[derive(Debug, Default, Clone)]
+struct Fetch {
+    last_fetch: Option<u64>,
+    last_success: Option<u64>,
+    last_error: Option<String>,
+    /// queries that failed in a row
+    failures: u32,
+    /// when the realm should be queried next
+    due: Option<Instant>,
+}
+
+/// Everything the tracker currently knows, for consumers outside the run loop
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// query interval (seconds)
+    pub interval: u64,
+    /// UNIX timestamp (seconds) of when the tracker started
+    pub started_at: u64,
+    pub realms: Vec<RealmSnapshot>,
+    /// database the history is recorded in, if any
+    #[serde(skip)]
+    pub history_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RealmSnapshot {
+    pub realm: Realm,
+    pub name: String,
+    pub regions: Vec<RegionSnapshot>,
+    /// UNIX timestamp (seconds) of the last query
+    pub last_fetch: Option<u64>,
+    /// UNIX timestamp (seconds) of the last successful query
+    pub last_success: Option<u64>,
+    /// error of the last query, if it failed
+    pub last_error: Option<String>,
+    /// queries that failed in a row
+    pub failures: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionSnapshot {
+    pub region: Region,
+    pub name: &'static str,
+    pub level: i32,
+    /// UNIX timestamp (seconds) of the last change seen since the tracker started
+    pub changed_at: Option<u64>,
+    /// UNIX timestamp (seconds) of when progress was last reported to the source
+    pub reported_at: Option<u64>,
+    /// whether there's been no new report for longer than `stale_after`
+    pub stale: bool,
+}
+
+/// Path of the history database, if history is enabled
+pub fn history_path(config: &Config) -> Option<Result<PathBuf>> {
+    if !config.history {
+        return None;
+    }
+
+    Some(match &config.history_file {
+        Some(path) => Ok(path.clone()),
+        None => history::default_path(),
+    })
+}
+
+/// How long ago (seconds) a report from `reported_at` was, if that's longer than
+/// `stale_after`. Reports that don't say when they were made are never stale.
+pub fn stale_age(config: &Config, reported_at: Option<u64>, now: u64) -> Option<u64> {
+    let age = now.saturating_sub(reported_at?);
+    (age > config.stale_after).then_some(age)
+}
+
+pub fn open_history(config: &Config) -> Option<History> {
+    match history_path(config)?.and_then(|path| History::open(&path)) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            log::error!("History is not recorded: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Open the history only to read estimates from, if it's been recorded before
+pub fn read_history(config: &Config) -> Option<History> {
+    let path = match history_path(config)? {
+        Ok(path) if path.exists() => path,
+        Ok(_) => return None,
+        Err(e) => {
+            log::warn!("Walks are not estimated: {:#}", e);
+            return None;
+        }
+    };
+
+    match History::open_read_only(&path) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            log::warn!("Walks are not estimated: {:#}", e);
+            None
+        }
+    }
+}
+
+impl Tracker {
+    /// Without `persist`, the saved state and history are only read, so that the
+    /// tracker can run alongside a daemon that keeps them.
+    pub fn new(
+        config: Config,
+        client: &Client,
+        metrics: Metrics,
+        events: broadcast::Sender<Event>,
+        persist: bool,
+    ) -> Result<Self> {
+        let state_path = match &config.state_file {
+            Some(path) => path.clone(),
+            None => state::default_path()?,
+        };
+
+        let max_age = Duration::from_secs(config.max_state_age * 60);
+        let state = State::load(&state_path, max_age).unwrap_or_else(|e| {
+            log::warn!("Ignoring saved state: {:#}", e);
+            State::default()
+        });
+
+        let statuses = config
+            .realms
+            .iter()
+            .map(|r| (r.realm, state.status(r.realm).unwrap_or_default()))
+            .collect();
+
+        let notifiers =
+            notifier::build(&config.notifiers, client.http(), &source::credits(&config))?;
+        let sources = Sources::new(&config);
+        let history = if persist {
+            open_history(&config)
+        } else {
+            read_history(&config)
+        };
+
+        let mut tracker = Tracker {
+            config,
+            statuses,
+            notifiers,
+            sources,
+            state_path,
+            pending_walks: Vec::new(),
+            history,
+            persist,
+            fetches: HashMap::new(),
+            started_at: state::now(),
+            metrics,
+            events,
+            changed_at: HashMap::new(),
+            conflicts: HashSet::new(),
+            reported_at: HashMap::new(),
+            stale: HashSet::new(),
+        };
+        tracker.stagger();
+
+        Ok(tracker)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Current levels and query outcomes of all tracked realms
+    pub fn snapshot(&self) -> Snapshot {
+        let now = state::now();
+        let realms = self
+            .config
+            .realms
+            .iter()
+            .zip(&self.statuses)
+            .map(|(RealmConfig { realm, regions, .. }, (_, status))| {
+                let fetch = self.fetches.get(realm).cloned().unwrap_or_default();
+                RealmSnapshot {
+                    realm: *realm,
+                    name: realm.to_string(),
+                    regions: regions
+                        .iter()
+                        .map(|&region| {
+                            let reported_at = self.reported_at.get(&(*realm, region)).copied();
+                            RegionSnapshot {
+                                region,
+                                name: region.name(),
+                                level: status.get(region),
+                                changed_at: self.changed_at.get(&(*realm, region)).copied(),
+                                reported_at,
+                                stale: stale_age(&self.config, reported_at, now).is_some(),
+                            }
+                        })
+                        .collect(),
+                    last_fetch: fetch.last_fetch,
+                    last_success: fetch.last_success,
+                    last_error: fetch.last_error,
+                    failures: fetch.failures,
+                }
+            })
+            .collect();
+
+        Snapshot {
+            interval: self.config.interval,
+            started_at: self.started_at,
+            realms,
+            history_file: history_path(&self.config).and_then(Result::ok),
+        }
+    }
+
+    /// Switch to a new configuration, keeping the statuses of realms that are still tracked.
+    ///
+    /// If the new configuration can't be applied, the old one stays in place.
+    pub fn reload(&mut self, config: Config, client: &mut Client) -> Result<()> {
+        let notifiers =
+            notifier::build(&config.notifiers, client.http(), &source::credits(&config))?;
+        let state_path = match &config.state_file {
+            Some(path) => path.clone(),
+            None => state::default_path()?,
+        };
+
+        let changes = self.config.diff(&config);
+        if changes.is_empty() {
+            log::info!("Configuration reloaded, nothing changed");
+        } else {
+            log::info!("Configuration reloaded:");
+            for change in changes {
+                log::info!("  {}", change);
+            }
+        }
+
+        self.statuses = config
+            .realms
+            .iter()
+            .map(|r| {
+                let status = self
+                    .statuses
+                    .iter()
+                    .find(|(realm, _)| *realm == r.realm)
+                    .map(|&(_, status)| status)
+                    .unwrap_or_default();
+                (r.realm, status)
+            })
+            .collect();
+
+        if config.history != self.config.history || config.history_file != self.config.history_file
+        {
+            self.history = if self.persist {
+                open_history(&config)
+            } else {
+                read_history(&config)
+            };
+        }
+
+        if config.cache != self.config.cache
+            || config.cache_ttl != self.config.cache_ttl
+            || config.cache_dir != self.config.cache_dir
+        {
+            client.set_cache(&config);
+        }
+
+        let interval = self.config.interval;
+        self.config = config;
+        self.notifiers = notifiers;
+        self.sources = Sources::new(&self.config);
+        self.state_path = state_path;
+        self.save();
+
+        // realms that are new are due right away, others keep their schedule
+        if self.config.interval != interval {
+            self.stagger();
+        }
+
+        Ok(())
+    }
+
+    /// Spread the queries of all realms evenly over the interval, starting now
+    fn stagger(&mut self) {
+        let now = Instant::now();
+        let spacing =
+            Duration::from_secs(self.config.interval) / self.config.realms.len().max(1) as u32;
+
+        for (i, RealmConfig { realm, .. }) in self.config.realms.iter().enumerate() {
+            self.fetches.entry(*realm).or_default().due = Some(now + spacing * i as u32);
+        }
+    }
+
+    /// When the next realm is due to be queried
+    pub fn next_poll(&self) -> Instant {
+        self.config
+            .realms
+            .iter()
+            .map(|r| {
+                self.fetches
+                    .get(&r.realm)
+                    .and_then(|fetch| fetch.due)
+                    .unwrap_or_else(Instant::now)
+            })
+            .min()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(self.config.interval))
+    }
+
+    /// Query the realms that are due, and notify about any changes
+    pub async fn poll(&mut self, client: &Client) -> Result<()> {
+        let previous = self.statuses.clone();
+        let now = Instant::now();
+
+        let realms = self.config.realms.iter().zip(self.statuses.iter_mut());
+        for (
+            RealmConfig {
+                realm,
+                regions,
+                sources,
+            },
+            (_, status),
+        ) in realms
+        {
+            let realm = *realm;
+            let fetch = self.fetches.entry(realm).or_default();
+            if fetch.due.is_some_and(|due| due > now) {
+                continue;
+            }
+            fetch.last_fetch = Some(state::now());
+
+            let start = Instant::now();
+            let mut reports = Vec::new();
+            let mut errors = Vec::new();
+            for source in self.sources.select(sources) {
+                match source.fetch(client, realm).await {
+                    Ok(progress) => reports.push((source.name(), progress)),
+                    Err(e) => {
+                        log::error!(
+                            "[{}] Failed to query progress from {}: {}",
+                            realm,
+                            source.name(),
+                            e
+                        );
+                        self.metrics.fetch_failed(realm, &e);
+                        errors.push(e);
+                    }
+                }
+            }
+
+            // as long as one source answers, the realm is still tracked
+            if reports.is_empty() {
+                let Some(e) = errors.last() else { continue };
+                fetch.last_error = Some(e.to_string());
+                fetch.failures += 1;
+
+                let delay = errors
+                    .iter()
+                    .map(|e| retry_delay(&self.config, fetch.failures, e))
+                    .max()
+                    .unwrap_or_default();
+                log::info!("[{}] Retrying in {}s", realm, delay.as_secs());
+                fetch.due = Some(Instant::now() + delay);
+
+                if fetch.failures == self.config.degraded_after {
+                    let event = Event::degraded(realm, fetch.failures, &e.to_string());
+                    log::warn!("{}: {}", event.title(), event.message());
+                    let _ = self.events.send(event.clone());
+                    self.notifiers.notify(&event);
+                }
+                continue;
+            }
+
+            let fetched_at = state::now();
+            self.metrics.fetch_succeeded(realm, fetched_at);
+            fetch.last_success = fetch.last_fetch;
+            fetch.last_error = None;
+            fetch.due = Some(start + Duration::from_secs(self.config.interval));
+
+            if fetch.failures >= self.config.degraded_after {
+                let event = Event::recovered(realm, fetch.failures);
+                log::info!("{}: {}", event.title(), event.message());
+                let _ = self.events.send(event.clone());
+                self.notifiers.notify(&event);
+            }
+            fetch.failures = 0;
+
+            let consensus = consensus::reconcile(&reports, self.config.max_report_age, fetched_at);
+
+            for region in Region::ALL {
+                let conflict = consensus
+                    .conflicts
+                    .iter()
+                    .find(|c| c.region == region && regions.contains(&region));
+
+                let Some(conflict) = conflict else {
+                    self.conflicts.remove(&(realm, region));
+                    continue;
+                };
+
+                // off by one is normal while a report is making its way around
+                if conflict.spread() <= 1 {
+                    log::debug!("[{}] Sources disagree on {}: {}", realm, region, conflict);
+                } else if self.conflicts.insert((realm, region)) {
+                    let event = Event::conflict(realm, region, conflict.to_string());
+                    log::warn!("{}: {}", event.title(), event.message());
+                    let _ = self.events.send(event.clone());
+                    self.notifiers.notify(&event);
+                } else {
+                    log::debug!(
+                        "[{}] Sources still disagree on {}: {}",
+                        realm,
+                        region,
+                        conflict
+                    );
+                }
+            }
+
+            let levels: Vec<Observation> = consensus
+                .progress
+                .into_iter()
+                .map(|progress| Observation {
+                    realm: progress.realm,
+                    region: progress.region,
+                    level: progress.level.get(),
+                    reported_at: progress.reported_at,
+                })
+                .collect();
+
+            if let Some(history) = self.history.as_mut().filter(|_| self.persist) {
+                if let Err(e) = history.record_observations(fetched_at, &levels) {
+                    log::error!("Failed to record history: {:#}", e);
+                }
+            }
+
+            // a response that lacks regions is suspicious, don't trust it with a walk
+            let complete = Region::ALL.iter().all(|region| {
+                levels.iter().any(|o| o.region == *region)
+                    || consensus.conflicts.iter().any(|c| c.region == *region)
+            });
+
+            for Observation {
+                region,
+                level: new,
+                reported_at,
+                ..
+            } in levels
+            {
+                if !regions.contains(&region) {
+                    continue;
+                }
+
+                if let Some(reported_at) = reported_at {
+                    self.reported_at.insert((realm, region), reported_at);
+                }
+
+                let stale = stale_age(&self.config, reported_at, fetched_at);
+                match stale {
+                    Some(age) if self.stale.insert((realm, region)) => log::warn!(
+                        "[{}] No progress reported for {} in {} minutes, {}/6 may be out of date",
+                        realm,
+                        region,
+                        age / 60,
+                        new
+                    ),
+                    // a report that doesn't say when it was made doesn't show it's fresh
+                    None if reported_at.is_some() && self.stale.remove(&(realm, region)) => {
+                        log::info!("[{}] Progress for {} is reported again", realm, region)
+                    }
+                    _ => {}
+                }
+
+                let old = status.get(region);
+                let pending = self
+                    .pending_walks
+                    .iter()
+                    .position(|&p| p == (realm, region));
+
+                if Event::is_walk(old, new) {
+                    if !complete {
+                        log::warn!(
+                            "[{}] Ignoring reset of {} from {} to {} in incomplete response",
+                            realm,
+                            region,
+                            old,
+                            new
+                        );
+                        continue;
+                    }
+
+                    if pending.is_none() {
+                        log::info!(
+                            "[{}] {} was reset from {} to {}, waiting for confirmation",
+                            realm,
+                            region,
+                            old,
+                            new
+                        );
+                        self.pending_walks.push((realm, region));
+                        continue;
+                    }
+                } else if pending.is_some() {
+                    log::warn!(
+                        "[{}] Reset of {} was not confirmed, now at {}",
+                        realm,
+                        region,
+                        new
+                    );
+                }
+
+                if let Some(i) = pending {
+                    self.pending_walks.remove(i);
+                }
+
+                let changed = status.update(realm, region, new)?;
+                self.metrics.set_progress(realm, region, status.get(region));
+
+                if let Some(mut event) = changed {
+                    self.changed_at.insert((realm, region), fetched_at);
+
+                    if let Some(history) = &self.history {
+                        let result = if self.persist {
+                            record_change(history, &mut event, reported_at)
+                        } else {
+                            estimate(history, &mut event)
+                        };
+                        if let Err(e) = result {
+                            log::error!("Failed to record history: {:#}", e);
+                        }
+                    }
+
+                    if let Some(age) = stale {
+                        match self.config.stale_notifications {
+                            StalePolicy::Send => {}
+                            StalePolicy::Soften => event.soften(age),
+                            StalePolicy::Suppress => {
+                                log::info!(
+                                    "Not notifying, the report is {} minutes old: {}",
+                                    age / 60,
+                                    event.title()
+                                );
+                                let _ = self.events.send(event);
+                                continue;
+                            }
+                        }
+                    }
+
+                    // there may be no one listening, which is fine
+                    let _ = self.events.send(event.clone());
+
+                    if rules::allows(&self.config.rules, &event) {
+                        self.notifiers.notify(&event);
+                    } else {
+                        log::debug!("No rule matches, not notifying: {}", event.title());
+                    }
+                }
+            }
+        }
+
+        if self.statuses != previous {
+            self.save();
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self) {
+        if !self.persist {
+            return;
+        }
+
+        if let Err(e) = State::new(&self.statuses).save(&self.state_path) {
+            log::error!("Failed to save state: {:#}", e);
+        }
+    }
+}
+
+/// How soon to query a realm again after `failures` failed queries in a row.
+///
+/// The delay backs off exponentially with some jitter, up to the interval, and
+/// retries stop after `fetch_retries` failures in a row. If the API asked to back
+/// off for longer, that's respected.
+fn retry_delay(config: &Config, failures: u32, error: &FetchError) -> Duration {
+    let interval = Duration::from_secs(config.interval);
+
+    let delay = if failures > config.fetch_retries {
+        interval
+    } else {
+        let backoff = RETRY_BACKOFF
+            .saturating_mul(1 << (failures - 1).min(16))
+            .min(interval);
+
+        // somewhere between half and all of the backoff
+        let random = RandomState::new().build_hasher().finish();
+        let jitter = random % (backoff.as_millis() as u64 / 2 + 1);
+        backoff / 2 + Duration::from_millis(jitter)
+    };
+
+    delay.max(error.retry_after().unwrap_or_default())
+}
+
+/// Record a change, and how long the previous level lasted, then estimate the walk
+fn record_change(history: &History, event: &mut Event, reported_at: Option<u64>) -> Result<()> {
+    let region = event
+        .region
+        .ok_or_else(|| anyhow!("only changes in progress are recorded"))?;
+    let now = state::now();
+    let advanced = event.new == event.old + 1 || event.kind == notifier::EventKind::Walk;
+
+    if advanced {
+        if let Some(started) = history.level_started(event.realm, region, event.old)? {
+            history.record_stage(event.realm, region, event.old, started, now)?;
+        }
+    }
+
+    history.record_change(event, reported_at)?;
+    estimate(history, event)
+}
+
+/// Estimate the walk from a change, which has just happened
+fn estimate(history: &History, event: &mut Event) -> Result<()> {
+    let region = event
+        .region
+        .ok_or_else(|| anyhow!("only changes in progress are estimated"))?;
+    event.estimate = eta::estimate(history, event.realm, region, event.new, Some(0))?;
+    Ok(())
+}
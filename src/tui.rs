@@ -0,0 +1,280 @@
+use crate::client::Client;
+use crate::config::{Config, NotifierConfig};
+use crate::metrics::Metrics;
+use crate::new_timer;
+use crate::notifier::{Event, Urgency};
+use crate::source;
+use crate::state;
+use crate::tracker::{RealmSnapshot, Snapshot, Tracker};
+use anyhow::Result;
+use crossterm::event::{Event as TermEvent, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+
+/// How many lines the event log keeps around
+const LOG_LINES: usize = 500;
+
+/// Lines shown in the event log, fed by both the logger and tracker events
+#[derive(Default)]
+pub struct EventLog {
+    lines: Mutex<VecDeque<(Color, String)>>,
+}
+
+impl EventLog {
+    fn push(&self, color: Color, text: String) {
+        let time = OffsetDateTime::now_utc()
+            .format(format_description!("[hour]:[minute]:[second]"))
+            .unwrap_or_default();
+
+        let mut lines = self.lines.lock().unwrap();
+        for line in text.lines() {
+            lines.push_back((color, format!("{} {}", time, line)));
+        }
+
+        while lines.len() > LOG_LINES {
+            lines.pop_front();
+        }
+    }
+
+    fn push_event(&self, event: &Event) {
+        let text = format!("{}: {}", event.title(), event.message().replace('\n', ", "));
+        self.push(urgency_color(Some(event.urgency)), text);
+    }
+}
+
+/// Logger that writes into the event log, since stderr belongs to the terminal UI
+struct Logger {
+    log: Arc<EventLog>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let color = match record.level() {
+            log::Level::Error => Color::Red,
+            log::Level::Warn => Color::Yellow,
+            _ => Color::Gray,
+        };
+
+        self.log
+            .push(color, format!("{:<5} {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install a logger that feeds the dashboard's event log
+pub fn init_logger() -> Result<Arc<EventLog>> {
+    let log = Arc::new(EventLog::default());
+    log::set_boxed_logger(Box::new(Logger { log: log.clone() }))?;
+    log::set_max_level(log::LevelFilter::Info);
+    Ok(log)
+}
+
+/// Track the configured realms and show them in the terminal until the user quits.
+///
+/// A daemon may well be tracking the same realms, so the dashboard leaves the saved
+/// state and history alone, and only notifies through exec hooks and webhooks if
+/// asked to with `notify`.
+pub async fn run(mut config: Config, log: Arc<EventLog>, notify: bool) -> Result<()> {
+    // the dashboard takes the place of notifiers that pop up or print to the terminal
+    config.notifiers.retain(|notifier| {
+        notify && !matches!(notifier, NotifierConfig::Desktop | NotifierConfig::Stdout)
+    });
+
+    let client = Client::new(&config)?;
+    let (events, mut receiver) = broadcast::channel(64);
+    let mut tracker = Tracker::new(config, &client, Metrics::new()?, events, false)?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut tracker, &client, &mut receiver, &log).await;
+    ratatui::restore();
+
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    tracker: &mut Tracker,
+    client: &Client,
+    receiver: &mut broadcast::Receiver<Event>,
+    log: &EventLog,
+) -> Result<()> {
+    let mut redraw = new_timer(1);
+    let mut input = EventStream::new();
+
+    log::info!("Data courtesy of {}", source::credits(tracker.config()));
+
+    loop {
+        terminal.draw(|frame| draw(frame, &tracker.snapshot(), log))?;
+
+        select! {
+            _ = tokio::time::sleep_until(tracker.next_poll()) => {
+                if let Err(e) = tracker.poll(client).await {
+                    log::error!("Failed to query progress: {:#}", e);
+                }
+            }
+
+            _ = redraw.tick() => {}
+
+            event = receiver.recv() => {
+                if let Ok(event) = event {
+                    log.push_event(&event);
+                }
+            }
+
+            input = input.next() => {
+                match input {
+                    Some(Ok(TermEvent::Key(key))) if key.kind == KeyEventKind::Press => {
+                        let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                        if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                            return Ok(());
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, snapshot: &Snapshot, log: &EventLog) {
+    // realms are laid out two per row
+    let rows: Vec<&[RealmSnapshot]> = snapshot.realms.chunks(2).collect();
+    let heights: Vec<u16> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|realm| realm.regions.len() as u16 + 2)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let [grid, events, help] = Layout::vertical([
+        Constraint::Length(heights.iter().sum()),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let now = state::now();
+    let row_areas = Layout::vertical(heights.into_iter().map(Constraint::Length)).split(grid);
+    for (row, area) in rows.iter().zip(row_areas.iter()) {
+        let columns = Layout::horizontal([Constraint::Ratio(1, 2); 2]).split(*area);
+        for (realm, area) in row.iter().zip(columns.iter()) {
+            draw_realm(frame, *area, realm, snapshot.interval, now);
+        }
+    }
+
+    let lines = log.lines.lock().unwrap();
+    let visible = events.height.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = lines
+        .iter()
+        .skip(lines.len().saturating_sub(visible))
+        .map(|(color, line)| ListItem::new(line.as_str()).style(Style::default().fg(*color)))
+        .collect();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Events ")),
+        events,
+    );
+
+    frame.render_widget(Paragraph::new(" q: quit").dark_gray(), help);
+}
+
+fn draw_realm(frame: &mut Frame, area: Rect, realm: &RealmSnapshot, interval: u64, now: u64) {
+    let status = match (&realm.last_error, realm.last_fetch) {
+        (Some(error), _) => Span::raw(format!(" {} ", error)).red(),
+        (None, Some(fetched)) => Span::raw(format!(" fetched {} ago ", ago(now, fetched))).green(),
+        (None, None) => Span::raw(" waiting for first fetch ").dark_gray(),
+    };
+
+    let stale = realm
+        .last_success
+        .is_none_or(|success| now.saturating_sub(success) > 3 * interval);
+    let block = Block::bordered()
+        .title(format!(" {} ", realm.name))
+        .title(Line::from(status).right_aligned())
+        .border_style(if stale && realm.last_fetch.is_some() {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        });
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = Layout::vertical(realm.regions.iter().map(|_| Constraint::Length(1))).split(inner);
+
+    for (region, area) in realm.regions.iter().zip(rows.iter()) {
+        let [name, gauge, changed] = Layout::horizontal([
+            Constraint::Length(10),
+            Constraint::Min(10),
+            Constraint::Length(16),
+        ])
+        .areas(*area);
+
+        let level = region.level.clamp(0, 6);
+        let (color, label) = if region.stale {
+            (Color::DarkGray, format!("{}/6 (stale)", level))
+        } else {
+            (
+                urgency_color(Urgency::for_level(level)),
+                format!("{}/6", level),
+            )
+        };
+        frame.render_widget(Paragraph::new(region.name), name);
+        frame.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(color))
+                .ratio(level as f64 / 6.0)
+                .label(label),
+            gauge,
+        );
+
+        let since = match region.changed_at {
+            Some(changed_at) => format!(" changed {} ago", ago(now, changed_at)),
+            None => " no change yet".to_owned(),
+        };
+        frame.render_widget(Paragraph::new(since).dark_gray(), changed);
+    }
+}
+
+fn urgency_color(urgency: Option<Urgency>) -> Color {
+    match urgency {
+        Some(Urgency::Low) => Color::Green,
+        Some(Urgency::Normal) => Color::Yellow,
+        Some(Urgency::Critical) => Color::Red,
+        None => Color::DarkGray,
+    }
+}
+
+/// Rough, short description of how long ago `then` was, e.g. `3h 12m`
+fn ago(now: u64, then: u64) -> String {
+    let secs = now.saturating_sub(then);
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}